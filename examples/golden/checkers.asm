@@ -0,0 +1,59 @@
+; fills the background with a checkerboard of a blank tile and one with four
+; bands of the shades, then spins. checkers.gb is this assembled with rgbds:
+; rgbasm -o checkers.o checkers.asm && rgblink -o checkers.gb checkers.o
+; rgbfix -v -p 0 -t CHECKERS -j checkers.gb
+; it's released into the public domain, like the frames checked against it
+
+SECTION "entry", ROM0[$100]
+    nop
+    jp Start
+
+SECTION "main", ROM0[$150]
+Start:
+    di
+    ld sp, $fffe
+.waitVBlank
+    ldh a, [$44]
+    cp 144
+    jr c, .waitVBlank
+    xor a
+    ldh [$40], a        ; lcd off so vram can be written
+
+    ld hl, $8000
+    ld de, Tiles
+    ld c, Tiles.end - Tiles
+.copyTiles
+    ld a, [de]
+    ld [hl+], a
+    inc de
+    dec c
+    jr nz, .copyTiles
+
+    ; tile (x + y) & 1 for the map entry at y * 32 + x
+    ld hl, $9800
+.fillMap
+    ld a, l
+    swap a
+    rrca
+    xor l
+    and 1
+    ld [hl+], a
+    ld a, h
+    cp $9c
+    jr nz, .fillMap
+
+    ld a, $e4
+    ldh [$47], a
+    xor a
+    ldh [$42], a
+    ldh [$43], a
+    ld a, $91           ; lcd on, tiles at $8000, background on
+    ldh [$40], a
+.done
+    jr .done
+
+Tiles:
+    ds 16, 0
+    db $00, $00, $00, $00, $ff, $00, $ff, $00
+    db $00, $ff, $00, $ff, $ff, $ff, $ff, $ff
+.end
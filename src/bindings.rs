@@ -0,0 +1,241 @@
+use crate::joypad::Button;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+
+// emulator controls, separate from the game boy's buttons
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Hotkey {
+    Rewind,
+    FastForward,
+    Pause,
+    FrameAdvance,
+    SpeedUp,
+    SlowDown,
+    SaveState,
+    LoadState,
+    Screenshot,
+    Reset,
+    ToggleBackground,
+    ToggleWindow,
+    ToggleSprites,
+    TogglePriority,
+}
+
+// which keyboard keys press which buttons and hotkeys, each can have several keys
+#[derive(Clone)]
+pub struct KeyBindings {
+    buttons: Vec<(minifb::Key, Button)>,
+    hotkeys: Vec<(minifb::Key, Hotkey)>,
+}
+
+impl KeyBindings {
+    pub fn new() -> Self {
+        use minifb::Key;
+        Self {
+            buttons: vec![
+                (Key::W, Button::Up),
+                (Key::A, Button::Left),
+                (Key::S, Button::Down),
+                (Key::D, Button::Right),
+                (Key::V, Button::Start),
+                (Key::B, Button::Select),
+                (Key::J, Button::A),
+                (Key::K, Button::B),
+            ],
+            hotkeys: vec![
+                (Key::Backspace, Hotkey::Rewind),
+                (Key::Tab, Hotkey::FastForward),
+                (Key::P, Hotkey::Pause),
+                (Key::N, Hotkey::FrameAdvance),
+                (Key::Equal, Hotkey::SpeedUp),
+                (Key::Minus, Hotkey::SlowDown),
+                (Key::F5, Hotkey::SaveState),
+                (Key::F8, Hotkey::LoadState),
+                (Key::F12, Hotkey::Screenshot),
+                (Key::F1, Hotkey::Reset),
+                (Key::F2, Hotkey::ToggleBackground),
+                (Key::F3, Hotkey::ToggleWindow),
+                (Key::F4, Hotkey::ToggleSprites),
+                (Key::F6, Hotkey::TogglePriority),
+            ],
+        }
+    }
+
+    // config.toml in the user's config directory, the defaults if there is none
+    pub fn load_config() -> Result<Self, String> {
+        match config_path() {
+            Some(path) if path.exists() => Self::from_file(&path.to_string_lossy()),
+            _ => Ok(Self::new()),
+        }
+    }
+
+    // bindings files are toml, a key name or a list of them for each button or hotkey,
+    // anything left out keeps its default keys:
+    // [buttons]
+    // a = ["j", "z"]
+    // [hotkeys]
+    // save_state = "f5"
+    pub fn from_file(path: &str) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+        let file: BindingsFile = toml::from_str(&text).map_err(|e| format!("{}: {}", path, e))?;
+        let mut bindings = Self::new();
+        for (name, keys) in file.buttons {
+            let button = button_from_name(&name).ok_or_else(|| format!("{}: unknown button {}", path, name))?;
+            let keys = keys.parse().map_err(|e| format!("{}: {}", path, e))?;
+            bindings.bind_button(button, keys);
+        }
+        for (name, keys) in file.hotkeys {
+            let hotkey = hotkey_from_name(&name).ok_or_else(|| format!("{}: unknown hotkey {}", path, name))?;
+            let keys = keys.parse().map_err(|e| format!("{}: {}", path, e))?;
+            bindings.hotkeys.retain(|(_, bound)| *bound != hotkey);
+            bindings.hotkeys.extend(keys.into_iter().map(|key| (key, hotkey)));
+        }
+        Ok(bindings)
+    }
+
+    // a comma separated list like "a=z,b=x,start=enter", each replaces the button's keys
+    pub fn parse(&mut self, bindings: &str) -> Result<(), String> {
+        for binding in bindings.split(',') {
+            let (button, key) = match binding.find('=') {
+                Some(index) => (&binding[..index], &binding[index + 1..]),
+                None => return Err(format!("key binding {} should look like button=key", binding)),
+            };
+            let button = button_from_name(button).ok_or_else(|| format!("unknown button {}", button))?;
+            let key = key_from_name(key).ok_or_else(|| format!("unknown key {}", key))?;
+            self.bind_button(button, vec![key]);
+        }
+        Ok(())
+    }
+
+    fn bind_button(&mut self, button: Button, keys: Vec<minifb::Key>) {
+        self.buttons.retain(|(_, bound)| *bound != button);
+        self.buttons.extend(keys.into_iter().map(|key| (key, button)));
+    }
+
+    pub fn button(&self, key: minifb::Key) -> Option<Button> {
+        self.buttons.iter().find(|(bound, _)| *bound == key).map(|(_, button)| *button)
+    }
+
+    pub fn hotkey(&self, key: minifb::Key) -> Option<Hotkey> {
+        self.hotkeys.iter().find(|(bound, _)| *bound == key).map(|(_, hotkey)| *hotkey)
+    }
+
+    pub fn hotkey_keys(&self, hotkey: Hotkey) -> impl Iterator<Item = minifb::Key> + '_ {
+        self.hotkeys.iter().filter(move |(_, bound)| *bound == hotkey).map(|(key, _)| *key)
+    }
+}
+
+pub fn config_path() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("rust-dmg").join("config.toml"))
+}
+
+#[derive(Deserialize)]
+struct BindingsFile {
+    #[serde(default)]
+    buttons: HashMap<String, KeyList>,
+    #[serde(default)]
+    hotkeys: HashMap<String, KeyList>,
+}
+
+// a name or a list of names in a config file
+#[derive(Deserialize)]
+#[serde(untagged)]
+pub enum KeyList {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl KeyList {
+    pub fn names(&self) -> &[String] {
+        match self {
+            KeyList::One(name) => std::slice::from_ref(name),
+            KeyList::Many(names) => names.as_slice(),
+        }
+    }
+
+    fn parse(&self) -> Result<Vec<minifb::Key>, String> {
+        self.names()
+            .iter()
+            .map(|name| key_from_name(name).ok_or_else(|| format!("unknown key {}", name)))
+            .collect()
+    }
+}
+
+pub fn button_from_name(name: &str) -> Option<Button> {
+    match name.to_lowercase().as_str() {
+        "up" => Some(Button::Up),
+        "down" => Some(Button::Down),
+        "left" => Some(Button::Left),
+        "right" => Some(Button::Right),
+        "start" => Some(Button::Start),
+        "select" => Some(Button::Select),
+        "a" => Some(Button::A),
+        "b" => Some(Button::B),
+        _ => None,
+    }
+}
+
+fn hotkey_from_name(name: &str) -> Option<Hotkey> {
+    match name.to_lowercase().as_str() {
+        "rewind" => Some(Hotkey::Rewind),
+        "fast_forward" => Some(Hotkey::FastForward),
+        "pause" => Some(Hotkey::Pause),
+        "frame_advance" => Some(Hotkey::FrameAdvance),
+        "speed_up" => Some(Hotkey::SpeedUp),
+        "slow_down" => Some(Hotkey::SlowDown),
+        "save_state" => Some(Hotkey::SaveState),
+        "load_state" => Some(Hotkey::LoadState),
+        "screenshot" => Some(Hotkey::Screenshot),
+        "reset" => Some(Hotkey::Reset),
+        "toggle_background" => Some(Hotkey::ToggleBackground),
+        "toggle_window" => Some(Hotkey::ToggleWindow),
+        "toggle_sprites" => Some(Hotkey::ToggleSprites),
+        "toggle_priority" => Some(Hotkey::TogglePriority),
+        _ => None,
+    }
+}
+
+pub fn key_from_name(name: &str) -> Option<minifb::Key> {
+    use minifb::Key::*;
+    let letters = [A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P, Q, R, S, T, U, V, W, X, Y, Z];
+    let digits = [Key0, Key1, Key2, Key3, Key4, Key5, Key6, Key7, Key8, Key9];
+    let functions = [F1, F2, F3, F4, F5, F6, F7, F8, F9, F10, F11, F12];
+    let name = name.to_lowercase();
+    let bytes = name.as_bytes();
+    if bytes.len() == 1 && bytes[0].is_ascii_lowercase() {
+        return Some(letters[(bytes[0] - b'a') as usize]);
+    }
+    if bytes.len() == 1 && bytes[0].is_ascii_digit() {
+        return Some(digits[(bytes[0] - b'0') as usize]);
+    }
+    if let Some(number) = name.strip_prefix('f').and_then(|number| number.parse::<usize>().ok()) {
+        return functions.get(number.wrapping_sub(1)).copied();
+    }
+    match name.as_str() {
+        "up" => Some(Up),
+        "down" => Some(Down),
+        "left" => Some(Left),
+        "right" => Some(Right),
+        "enter" => Some(Enter),
+        "space" => Some(Space),
+        "backspace" => Some(Backspace),
+        "tab" => Some(Tab),
+        "escape" => Some(Escape),
+        "lshift" => Some(LeftShift),
+        "rshift" => Some(RightShift),
+        "lctrl" => Some(LeftCtrl),
+        "rctrl" => Some(RightCtrl),
+        "lalt" => Some(LeftAlt),
+        "ralt" => Some(RightAlt),
+        "comma" => Some(Comma),
+        "period" => Some(Period),
+        "slash" => Some(Slash),
+        "semicolon" => Some(Semicolon),
+        "apostrophe" => Some(Apostrophe),
+        "minus" => Some(Minus),
+        "equal" => Some(Equal),
+        _ => None,
+    }
+}
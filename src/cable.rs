@@ -0,0 +1,204 @@
+use crate::gameboy::{Gameboy, CLOCKS_PER_FRAME};
+use crate::pacer::FramePacer;
+use crate::serial::Link;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+// state of the wires between two consoles in the same process
+struct Wire {
+    sb: [u8; 2],                // last byte each side offered when polled
+    sending: [bool; 2],         // side is clocking out a byte of its own
+    incoming: [Option<u8>; 2],  // byte clocked in by the other side
+}
+
+pub struct CableEnd {
+    side: usize,
+    wire: Rc<RefCell<Wire>>,
+}
+
+// a link cable with both ends in this process
+pub fn cable() -> (CableEnd, CableEnd) {
+    let wire = Rc::new(RefCell::new(Wire {
+        sb: [0xff; 2],
+        sending: [false; 2],
+        incoming: [None; 2],
+    }));
+    (
+        CableEnd { side: 0, wire: wire.clone() },
+        CableEnd { side: 1, wire },
+    )
+}
+
+impl Link for CableEnd {
+    fn transfer(&mut self, byte: u8) -> u8 {
+        let mut wire = self.wire.borrow_mut();
+        let other = 1 - self.side;
+        wire.sending[self.side] = true;
+        // both sides are providing a clock, neither sees the other's byte
+        if wire.sending[other] {
+            return 0xff;
+        }
+        wire.incoming[other] = Some(byte);
+        wire.sb[other]
+    }
+
+    fn poll(&mut self, byte: u8) -> Option<u8> {
+        let mut wire = self.wire.borrow_mut();
+        wire.sending[self.side] = false;
+        wire.sb[self.side] = byte;
+        wire.incoming[self.side].take()
+    }
+}
+
+// two consoles connected by a cable, stepped so neither gets ahead of the other
+// by more than an instruction
+pub struct LinkedGameboys {
+    pub left: Gameboy,
+    pub right: Gameboy,
+    left_clocks: u32,
+    right_clocks: u32,
+}
+
+impl LinkedGameboys {
+    pub fn new(mut left: Gameboy, mut right: Gameboy) -> Self {
+        let (left_end, right_end) = cable();
+        left.connect_link(Box::new(left_end));
+        right.connect_link(Box::new(right_end));
+        left.set_linked(true);
+        right.set_linked(true);
+        Self {
+            left,
+            right,
+            left_clocks: 0,
+            right_clocks: 0,
+        }
+    }
+
+    // the left window's hotkeys control both consoles, save states and resets are ignored
+    pub fn emulate(&mut self, pacer: &mut FramePacer) {
+        while self.left.is_open() && self.right.is_open() {
+            if pacer.frame_due() {
+                self.run_frame();
+            } else {
+                self.left.poll_display();
+                self.right.poll_display();
+            }
+            self.left.handle_hotkeys(pacer);
+            pacer.wait();
+        }
+    }
+
+    pub fn run_frame(&mut self) {
+        self.left.begin_frame();
+        self.right.begin_frame();
+        while self.left_clocks < CLOCKS_PER_FRAME || self.right_clocks < CLOCKS_PER_FRAME {
+            self.step();
+        }
+        self.left_clocks -= CLOCKS_PER_FRAME;
+        self.right_clocks -= CLOCKS_PER_FRAME;
+        self.left.end_frame();
+        self.right.end_frame();
+    }
+
+    // runs an instruction on whichever console is behind
+    pub fn step(&mut self) {
+        if self.left_clocks <= self.right_clocks {
+            self.left_clocks += self.left.step();
+        } else {
+            self.right_clocks += self.right.step();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::headless_display::HeadlessDisplay;
+
+    #[test]
+    fn each_side_gets_the_other_sides_byte() {
+        let (mut left, mut right) = cable();
+        assert_eq!(left.poll(0x12), None);
+        assert_eq!(right.poll(0x34), None);
+
+        // left clocks, right is waiting on the external clock
+        assert_eq!(left.transfer(0x12), 0x34);
+        assert_eq!(right.poll(0x34), Some(0x12));
+        assert_eq!(right.poll(0x34), None);
+        assert_eq!(left.poll(0x78), None);
+
+        // and the other way around
+        assert_eq!(right.transfer(0x56), 0x78);
+        assert_eq!(left.poll(0x78), Some(0x56));
+    }
+
+    #[test]
+    fn clocking_while_the_other_side_clocks_reads_0xff() {
+        let (mut left, mut right) = cable();
+        left.transfer(0x12);
+        assert_eq!(right.transfer(0x34), 0xff);
+        assert_eq!(left.poll(0x12), None);
+        // the byte left clocked out first still arrives, a clocking side ignores it
+        assert_eq!(right.poll(0x34), Some(0x12));
+    }
+
+    // a 32k rom with no mapper that runs the given code from 0x100
+    fn rom(name: &str, code: &[u8]) -> String {
+        let mut rom = vec![0; 0x8000];
+        rom[0x100..0x100 + code.len()].copy_from_slice(code);
+        let name = format!("rust-dmg-cable-{}-{}.gb", std::process::id(), name);
+        let path = std::env::temp_dir().join(name);
+        std::fs::write(&path, rom).unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    // two consoles don't fit on the default test thread stack in debug builds
+    fn with_big_stack(test: fn()) {
+        let thread = std::thread::Builder::new().stack_size(64 << 20).spawn(test);
+        thread.unwrap().join().unwrap();
+    }
+
+    #[test]
+    fn linked_consoles_swap_serial_bytes() {
+        with_big_stack(swap_serial_bytes);
+    }
+
+    fn swap_serial_bytes() {
+        let left = rom(
+            "left",
+            &[
+                0x06, 0x00, // ld b, 0
+                0x05, // dec b, so the right side is listening before we clock
+                0x20, 0xfd, // jr nz, -3
+                0x3e, 0x12, // ld a, 0x12
+                0xe0, 0x01, // ldh [sb], a
+                0x3e, 0x81, // ld a, 0x81
+                0xe0, 0x02, // ldh [sc], a
+                0x18, 0xfe, // jr -2
+            ],
+        );
+        let right = rom(
+            "right",
+            &[
+                0x3e, 0x34, // ld a, 0x34
+                0xe0, 0x01, // ldh [sb], a
+                0x3e, 0x80, // ld a, 0x80, waiting on the external clock
+                0xe0, 0x02, // ldh [sc], a
+                0x18, 0xfe, // jr -2
+            ],
+        );
+        let mut linked = LinkedGameboys::new(
+            Gameboy::new(&left, Box::new(HeadlessDisplay)).unwrap(),
+            Gameboy::new(&right, Box::new(HeadlessDisplay)).unwrap(),
+        );
+        let _ = std::fs::remove_file(left);
+        let _ = std::fs::remove_file(right);
+        linked.run_frame();
+
+        assert_eq!(linked.left.mmu().peek(0xff01), 0x34);
+        assert_eq!(linked.right.mmu().peek(0xff01), 0x12);
+        // both transfers are done
+        assert_eq!(linked.left.mmu().peek(0xff02) & 0x80, 0);
+        assert_eq!(linked.right.mmu().peek(0xff02) & 0x80, 0);
+    }
+}
@@ -0,0 +1,291 @@
+use crate::ppu::{DebugPalette, Layers};
+use crate::tile_viewer;
+
+pub const USAGE: &str = "usage: rust-dmg [run] <rom> [options]
+       rust-dmg info <rom>
+       rust-dmg disasm <rom> [--start <offset>] [--count <instructions>]
+       rust-dmg test <rom> [--frames <frames>]
+       rust-dmg golden <manifest> [--diff-dir <directory>] [--bless]
+
+run options:
+  --scale <1|2|4|8>          window scale
+  --palette <name|file>      grey, green, pocket, light or a palette file
+  --boot-rom <file>          runs the boot rom before the game
+  --save-dir <directory>     where battery saves, save states, screenshots and printouts go, the rom's directory by default
+  --speed <multiplier>       speed of the emulation, or uncapped
+  --frames <frames>          runs this many frames without a window and exits
+  --screenshot <file>        saves the last frame as a png on exit
+  --screenshot-at <frame>    saves the screenshot after this frame instead, without a window
+  --screenshot-scale <n>     screenshots are n times the native size, the f12 ones too
+  --trace <file>             writes the registers before every instruction
+  --config <file>            key bindings file instead of the one in the config directory
+  --keys <bindings>          like a=z,b=x,start=enter
+  --border                   draws the super game boy border
+  --link <link>              stdout, loopback, printer, printer:dir, listen:address,
+                             connect:address or local:rom
+  --load-state <file>        starts from a save state
+  --play <file>              plays a movie back
+  --record <file>            records a movie
+  --input-script <file>      presses buttons at the frames listed in a file
+  --video <file>             records every frame to a .gif, .png, .apng or .y4m
+  --tile-viewer              shows the tiles in vram in a second window
+  --dump-tiles <file>        saves the tiles in vram as a png on exit
+  --tile-palette <palette>   bgp, obp0, obp1 or grey for the tile viewer and dumps
+  --tilemap-viewer           shows both tilemaps with the screen's scroll and window in a second window
+  --dump-tilemaps <file>     saves both tilemaps as a png on exit
+  --oam-viewer               shows the sprites in oam and the lines that drew them in a second window
+  --dump-oam <file>          writes the oam entries and the lines that drew them on exit
+  --hide <layers>            hides layers for debugging, like background,window,sprites,priority
+  --memory-viewer            shows memory in a second window, bytes can be edited
+  --watch <file>             labeled addresses shown in the memory viewer, headless runs print their changes";
+
+pub enum Command {
+    Run(Box<RunOptions>),
+    Info { rom: String },
+    Disasm { rom: String, start: usize, count: usize },
+    Test { rom: String, frames: u32 },
+    Golden { manifest: String, diff_dir: Option<String>, bless: bool },
+    Help,
+}
+
+pub struct RunOptions {
+    pub rom: String,
+    pub scale: usize,
+    pub palette: Option<String>,
+    pub boot_rom: Option<String>,
+    pub save_dir: Option<String>,
+    pub speed: Option<f64>, // none is uncapped
+    pub frames: Option<u32>,
+    pub screenshot: Option<String>,
+    pub screenshot_at: Option<u32>,
+    pub screenshot_scale: usize,
+    pub trace: Option<String>,
+    pub config: Option<String>,
+    pub keys: Option<String>,
+    pub border: bool,
+    pub link: Option<String>,
+    pub load_state: Option<String>,
+    pub play: Option<String>,
+    pub record: Option<String>,
+    pub input_script: Option<String>,
+    pub video: Option<String>,
+    pub tile_viewer: bool,
+    pub dump_tiles: Option<String>,
+    pub tile_palette: DebugPalette,
+    pub tilemap_viewer: bool,
+    pub dump_tilemaps: Option<String>,
+    pub oam_viewer: bool,
+    pub dump_oam: Option<String>,
+    pub layers: Layers,
+    pub memory_viewer: bool,
+    pub watch: Option<String>,
+}
+
+impl RunOptions {
+    fn new(rom: String) -> Self {
+        Self {
+            rom,
+            scale: 1,
+            palette: None,
+            boot_rom: None,
+            save_dir: None,
+            speed: Some(1.0),
+            frames: None,
+            screenshot: None,
+            screenshot_at: None,
+            screenshot_scale: 1,
+            trace: None,
+            config: None,
+            keys: None,
+            border: false,
+            link: None,
+            load_state: None,
+            play: None,
+            record: None,
+            input_script: None,
+            video: None,
+            tile_viewer: false,
+            dump_tiles: None,
+            tile_palette: DebugPalette::Bgp,
+            tilemap_viewer: false,
+            dump_tilemaps: None,
+            oam_viewer: false,
+            dump_oam: None,
+            layers: Layers::new(),
+            memory_viewer: false,
+            watch: None,
+        }
+    }
+}
+
+// arguments without the program name
+pub fn parse(args: &[String]) -> Result<Command, String> {
+    let mut args = args.iter().map(|arg| arg.as_str());
+    match args.next() {
+        None | Some("help") | Some("--help") | Some("-h") => Ok(Command::Help),
+        Some("run") => parse_run(rom(&mut args)?, args),
+        Some("info") => {
+            let rom = rom(&mut args)?;
+            no_more_options(args)?;
+            Ok(Command::Info { rom })
+        }
+        Some("disasm") => {
+            let rom = rom(&mut args)?;
+            let mut start = 0x100;
+            let mut count = 32;
+            while let Some(arg) = args.next() {
+                match arg {
+                    "--start" => start = parse_address(value(&mut args, arg)?)?,
+                    "--count" => count = parse_number(value(&mut args, arg)?, arg)?,
+                    _ => return Err(format!("unknown option {}", arg)),
+                }
+            }
+            Ok(Command::Disasm { rom, start, count })
+        }
+        Some("test") => {
+            let rom = rom(&mut args)?;
+            let mut frames = 6000;
+            while let Some(arg) = args.next() {
+                match arg {
+                    "--frames" => frames = parse_number(value(&mut args, arg)?, arg)?,
+                    _ => return Err(format!("unknown option {}", arg)),
+                }
+            }
+            Ok(Command::Test { rom, frames })
+        }
+        Some("golden") => {
+            let manifest = match args.next() {
+                Some(manifest) if !manifest.starts_with('-') => manifest.to_string(),
+                _ => return Err("missing manifest path".to_string()),
+            };
+            let mut diff_dir = None;
+            let mut bless = false;
+            while let Some(arg) = args.next() {
+                match arg {
+                    "--diff-dir" => diff_dir = Some(value(&mut args, arg)?.to_string()),
+                    "--bless" => bless = true,
+                    _ => return Err(format!("unknown option {}", arg)),
+                }
+            }
+            Ok(Command::Golden { manifest, diff_dir, bless })
+        }
+        // a rom on its own runs it
+        Some(arg) if !arg.starts_with('-') => parse_run(arg.to_string(), args),
+        Some(arg) => Err(format!("unknown command {}", arg)),
+    }
+}
+
+fn parse_run<'a>(rom: String, mut args: impl Iterator<Item = &'a str>) -> Result<Command, String> {
+    let mut options = RunOptions::new(rom);
+    while let Some(arg) = args.next() {
+        match arg {
+            "--scale" => {
+                options.scale = match value(&mut args, arg)? {
+                    "1" => 1,
+                    "2" => 2,
+                    "4" => 4,
+                    "8" => 8,
+                    scale => return Err(format!("scale should be 1, 2, 4 or 8, not {}", scale)),
+                }
+            }
+            "--palette" => options.palette = Some(value(&mut args, arg)?.to_string()),
+            "--boot-rom" => options.boot_rom = Some(value(&mut args, arg)?.to_string()),
+            "--save-dir" => options.save_dir = Some(value(&mut args, arg)?.to_string()),
+            "--speed" => {
+                options.speed = match value(&mut args, arg)? {
+                    "uncapped" => None,
+                    speed => match speed.parse::<f64>() {
+                        Ok(speed) if speed > 0.0 => Some(speed),
+                        _ => return Err(format!("speed should be a positive number or uncapped, not {}", speed)),
+                    },
+                }
+            }
+            "--frames" => options.frames = Some(parse_number(value(&mut args, arg)?, arg)?),
+            "--screenshot" => options.screenshot = Some(value(&mut args, arg)?.to_string()),
+            "--screenshot-at" => options.screenshot_at = Some(parse_number(value(&mut args, arg)?, arg)?),
+            "--screenshot-scale" => {
+                options.screenshot_scale = match parse_number(value(&mut args, arg)?, arg)? {
+                    scale @ 1..=8 => scale,
+                    scale => return Err(format!("screenshot scale should be 1 to 8, not {}", scale)),
+                }
+            }
+            "--trace" => options.trace = Some(value(&mut args, arg)?.to_string()),
+            "--config" => options.config = Some(value(&mut args, arg)?.to_string()),
+            "--keys" => options.keys = Some(value(&mut args, arg)?.to_string()),
+            "--border" => options.border = true,
+            "--link" => options.link = Some(value(&mut args, arg)?.to_string()),
+            "--load-state" => options.load_state = Some(value(&mut args, arg)?.to_string()),
+            "--play" => options.play = Some(value(&mut args, arg)?.to_string()),
+            "--record" => options.record = Some(value(&mut args, arg)?.to_string()),
+            "--input-script" => options.input_script = Some(value(&mut args, arg)?.to_string()),
+            "--video" => options.video = Some(value(&mut args, arg)?.to_string()),
+            "--tile-viewer" => options.tile_viewer = true,
+            "--tilemap-viewer" => options.tilemap_viewer = true,
+            "--oam-viewer" => options.oam_viewer = true,
+            "--dump-oam" => options.dump_oam = Some(value(&mut args, arg)?.to_string()),
+            "--memory-viewer" => options.memory_viewer = true,
+            "--watch" => options.watch = Some(value(&mut args, arg)?.to_string()),
+            "--hide" => {
+                for layer in value(&mut args, arg)?.split(',') {
+                    match layer {
+                        "background" | "bg" => options.layers.background = false,
+                        "window" => options.layers.window = false,
+                        "sprites" => options.layers.sprites = false,
+                        "priority" => options.layers.cgb_priority = false,
+                        _ => return Err(format!("unknown layer {}, use background, window, sprites or priority", layer)),
+                    }
+                }
+            }
+            "--dump-tilemaps" => options.dump_tilemaps = Some(value(&mut args, arg)?.to_string()),
+            "--dump-tiles" => options.dump_tiles = Some(value(&mut args, arg)?.to_string()),
+            "--tile-palette" => {
+                let palette = value(&mut args, arg)?;
+                options.tile_palette = tile_viewer::palette_from_name(palette)
+                    .ok_or_else(|| format!("tile palette should be bgp, obp0, obp1 or grey, not {}", palette))?;
+            }
+            _ => return Err(format!("unknown option {}", arg)),
+        }
+    }
+    // capturing a frame runs headless up to it unless more frames are asked for
+    if let Some(frame) = options.screenshot_at {
+        if options.screenshot.is_none() {
+            return Err("--screenshot-at needs --screenshot".to_string());
+        }
+        match options.frames {
+            Some(frames) if frames < frame => {
+                return Err(format!("--screenshot-at {} is after the last frame {}", frame, frames))
+            }
+            Some(_) => (),
+            None => options.frames = Some(frame),
+        }
+    }
+    Ok(Command::Run(Box::new(options)))
+}
+
+fn rom<'a>(args: &mut impl Iterator<Item = &'a str>) -> Result<String, String> {
+    match args.next() {
+        Some(rom) if !rom.starts_with('-') => Ok(rom.to_string()),
+        _ => Err("missing rom path".to_string()),
+    }
+}
+
+fn value<'a>(args: &mut impl Iterator<Item = &'a str>, option: &str) -> Result<&'a str, String> {
+    args.next().ok_or_else(|| format!("{} needs a value", option))
+}
+
+fn no_more_options<'a>(mut args: impl Iterator<Item = &'a str>) -> Result<(), String> {
+    match args.next() {
+        Some(arg) => Err(format!("unknown option {}", arg)),
+        None => Ok(()),
+    }
+}
+
+fn parse_number<T: std::str::FromStr>(value: &str, option: &str) -> Result<T, String> {
+    value.parse().map_err(|_| format!("{} should be a number, not {}", option, value))
+}
+
+// hex, with or without a 0x or $ prefix
+fn parse_address(value: &str) -> Result<usize, String> {
+    let digits = value.trim_start_matches("0x").trim_start_matches('$');
+    usize::from_str_radix(digits, 16).map_err(|_| format!("{} is not a hex address", value))
+}
@@ -1,4 +1,5 @@
 use crate::mmu::MMU;
+use crate::state::{StateReader, StateWriter};
 pub struct CPU {
     pub a: u8,
     pub f: u8,
@@ -25,22 +26,98 @@ enum Flag {
 use Flag::{C, H, N, Z};
 
 impl CPU {
-    pub fn new(rom_path: &str) -> Self {
-        Self {
-            a: 0x01,
-            f: 0xB0,
-            b: 0x00,
-            c: 0x13,
-            d: 0x00,
-            e: 0xD8,
-            h: 0x01,
-            l: 0x4D,
-            pc: 0x0100,
-            sp: 0xfffe,
-            ime: false,
-            halted: false,
-            mmu: MMU::new(rom_path),
+    pub fn new(rom: Vec<u8>) -> Result<Self, String> {
+        let mmu = MMU::new(rom)?;
+        // register values left behind by the boot rom
+        Ok(if mmu.cgb {
+            Self {
+                a: 0x11,
+                f: 0x80,
+                b: 0x00,
+                c: 0x00,
+                d: 0xff,
+                e: 0x56,
+                h: 0x00,
+                l: 0x0d,
+                pc: 0x0100,
+                sp: 0xfffe,
+                ime: false,
+                halted: false,
+                mmu,
+            }
+        } else {
+            Self {
+                a: 0x01,
+                f: 0xB0,
+                b: 0x00,
+                c: 0x13,
+                d: 0x00,
+                e: 0xD8,
+                h: 0x01,
+                l: 0x4D,
+                pc: 0x0100,
+                sp: 0xfffe,
+                ime: false,
+                halted: false,
+                mmu,
+            }
+        })
+    }
+
+    // starts from address 0 with the boot rom mapped, it sets up everything itself
+    pub fn run_boot_rom(&mut self, boot_rom: Vec<u8>) -> Result<(), String> {
+        self.mmu.map_boot_rom(boot_rom)?;
+        self.a = 0;
+        self.f = 0;
+        self.b = 0;
+        self.c = 0;
+        self.d = 0;
+        self.e = 0;
+        self.h = 0;
+        self.l = 0;
+        self.sp = 0;
+        self.pc = 0;
+        Ok(())
+    }
+
+    // registers and the bytes at pc, one line per instruction
+    pub fn trace_line(&self) -> String {
+        format!(
+            "A:{:02X} F:{:02X} B:{:02X} C:{:02X} D:{:02X} E:{:02X} H:{:02X} L:{:02X} SP:{:04X} PC:{:04X} PCMEM:{:02X},{:02X},{:02X},{:02X}",
+            self.a, self.f, self.b, self.c, self.d, self.e, self.h, self.l, self.sp, self.pc,
+            self.mmu.read_byte(self.pc),
+            self.mmu.read_byte(self.pc.wrapping_add(1)),
+            self.mmu.read_byte(self.pc.wrapping_add(2)),
+            self.mmu.read_byte(self.pc.wrapping_add(3)),
+        )
+    }
+
+    pub fn save_state(&self, state: &mut StateWriter) {
+        for register in [self.a, self.f, self.b, self.c, self.d, self.e, self.h, self.l].iter() {
+            state.write_u8(*register);
         }
+        state.write_u16(self.sp);
+        state.write_u16(self.pc);
+        state.write_bool(self.ime);
+        state.write_bool(self.halted);
+        self.mmu.save_state(state);
+    }
+
+    // fields are written as they're read, load into a fresh cpu so a bad state can be thrown away
+    pub fn load_state(&mut self, state: &mut StateReader) -> Result<(), String> {
+        self.a = state.read_u8()?;
+        self.f = state.read_u8()? & 0xf0;
+        self.b = state.read_u8()?;
+        self.c = state.read_u8()?;
+        self.d = state.read_u8()?;
+        self.e = state.read_u8()?;
+        self.h = state.read_u8()?;
+        self.l = state.read_u8()?;
+        self.sp = state.read_u16()?;
+        self.pc = state.read_u16()?;
+        self.ime = state.read_bool()?;
+        self.halted = state.read_bool()?;
+        self.mmu.load_state(state)
     }
 
     pub fn do_cycle(&mut self) -> u32 {
@@ -51,8 +128,14 @@ impl CPU {
         } else {
             clocks += 4;
         }
+        clocks += self.mmu.take_hdma_stall();
         self.mmu.tick(clocks);
-        clocks
+        // return clocks at normal speed so frames keep their length
+        if self.mmu.double_speed {
+            clocks / 2
+        } else {
+            clocks
+        }
     }
 
     fn handle_interupts(&mut self) -> u32 {
@@ -233,7 +316,10 @@ impl CPU {
                 self.write_flag(Z, false);
                 4
             }
-            0x10 => 4,
+            0x10 => {
+                self.mmu.switch_speed();
+                4
+            }
             0x11 => {
                 let d16 = self.fetch_word();
                 self.write_de(d16);
@@ -0,0 +1,62 @@
+use crate::mmu::MMU;
+
+// something drawn from memory in a window of its own
+pub trait DebugView {
+    fn title(&self) -> String; // can change with the view's settings
+    fn size(&self) -> (usize, usize);
+    fn render(&self, mmu: &MMU) -> Vec<u32>;
+
+    // keys pressed while the view's window has focus
+    fn key_pressed(&mut self, _mmu: &mut MMU, _key: minifb::Key) {}
+
+    // what is under the mouse, shown in the title
+    fn hover(&self, _mmu: &MMU, _x: usize, _y: usize) -> Option<String> {
+        None
+    }
+}
+
+// a second window next to the game's, redrawn after every frame
+pub struct DebugWindow {
+    window: minifb::Window,
+    view: Box<dyn DebugView>,
+    title: String,
+}
+
+impl DebugWindow {
+    pub fn new(view: Box<dyn DebugView>) -> Result<Self, String> {
+        let (width, height) = view.size();
+        let options = minifb::WindowOptions {
+            scale: minifb::Scale::X2,
+            ..minifb::WindowOptions::default()
+        };
+        let title = view.title();
+        let window = minifb::Window::new(&title, width, height, options).map_err(|e| e.to_string())?;
+        Ok(Self { window, view, title })
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.window.is_open()
+    }
+
+    pub fn update(&mut self, mmu: &mut MMU) {
+        for key in self.window.get_keys_pressed(minifb::KeyRepeat::No).unwrap_or_default() {
+            self.view.key_pressed(mmu, key);
+        }
+        let hover = self
+            .window
+            .get_mouse_pos(minifb::MouseMode::Discard)
+            .and_then(|(x, y)| self.view.hover(mmu, x as usize, y as usize));
+        let title = match hover {
+            Some(hover) => format!("{} - {}", self.view.title(), hover),
+            None => self.view.title(),
+        };
+        if title != self.title {
+            self.window.set_title(&title);
+            self.title = title;
+        }
+        let (width, height) = self.view.size();
+        if let Err(e) = self.window.update_with_buffer(&self.view.render(mmu), width, height) {
+            println!("{}", e);
+        }
+    }
+}
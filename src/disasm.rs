@@ -0,0 +1,87 @@
+// decodes instructions from the bit fields of the opcode, xxyyyzzz with y split into ppq
+const R: [&str; 8] = ["b", "c", "d", "e", "h", "l", "(hl)", "a"];
+const RP: [&str; 4] = ["bc", "de", "hl", "sp"];
+const RP2: [&str; 4] = ["bc", "de", "hl", "af"];
+const CC: [&str; 4] = ["nz", "z", "nc", "c"];
+const ALU: [&str; 8] = ["add a,", "adc a,", "sub ", "sbc a,", "and ", "xor ", "or ", "cp "];
+const ROT: [&str; 8] = ["rlc", "rrc", "rl", "rr", "sla", "sra", "swap", "srl"];
+
+// returns the instruction at address and its length
+pub fn disassemble(read: impl Fn(u16) -> u8, address: u16) -> (String, u16) {
+    let opcode = read(address);
+    let d8 = read(address.wrapping_add(1));
+    let d16 = d8 as u16 | (read(address.wrapping_add(2)) as u16) << 8;
+    // relative jumps are shown with their target
+    let r8 = address.wrapping_add(2).wrapping_add(d8 as i8 as u16);
+    let (x, y, z) = (opcode >> 6, (opcode >> 3) & 0x7, opcode & 0x7);
+    let (p, q) = ((y >> 1) as usize, y & 0x1);
+    let (y, z) = (y as usize, z as usize);
+
+    let (text, length) = match (x, z) {
+        (0, 0) => match y {
+            0 => ("nop".to_string(), 1),
+            1 => (format!("ld (${:04x}),sp", d16), 3),
+            2 => ("stop".to_string(), 2),
+            3 => (format!("jr ${:04x}", r8), 2),
+            _ => (format!("jr {},${:04x}", CC[y - 4], r8), 2),
+        },
+        (0, 1) if q == 0 => (format!("ld {},${:04x}", RP[p], d16), 3),
+        (0, 1) => (format!("add hl,{}", RP[p]), 1),
+        (0, 2) => {
+            let memory = ["(bc)", "(de)", "(hl+)", "(hl-)"][p];
+            if q == 0 {
+                (format!("ld {},a", memory), 1)
+            } else {
+                (format!("ld a,{}", memory), 1)
+            }
+        }
+        (0, 3) => (format!("{} {}", if q == 0 { "inc" } else { "dec" }, RP[p]), 1),
+        (0, 4) => (format!("inc {}", R[y]), 1),
+        (0, 5) => (format!("dec {}", R[y]), 1),
+        (0, 6) => (format!("ld {},${:02x}", R[y], d8), 2),
+        (0, _) => (["rlca", "rrca", "rla", "rra", "daa", "cpl", "scf", "ccf"][y].to_string(), 1),
+        (1, 6) if y == 6 => ("halt".to_string(), 1),
+        (1, _) => (format!("ld {},{}", R[y], R[z]), 1),
+        (2, _) => (format!("{}{}", ALU[y], R[z]), 1),
+        (_, 0) => match y {
+            0..=3 => (format!("ret {}", CC[y]), 1),
+            4 => (format!("ldh ($ff{:02x}),a", d8), 2),
+            5 => (format!("add sp,{}", d8 as i8), 2),
+            6 => (format!("ldh a,($ff{:02x})", d8), 2),
+            _ => (format!("ld hl,sp{:+}", d8 as i8), 2),
+        },
+        (_, 1) if q == 0 => (format!("pop {}", RP2[p]), 1),
+        (_, 1) => (["ret", "reti", "jp hl", "ld sp,hl"][p].to_string(), 1),
+        (_, 2) => match y {
+            0..=3 => (format!("jp {},${:04x}", CC[y], d16), 3),
+            4 => ("ld ($ff00+c),a".to_string(), 1),
+            5 => (format!("ld (${:04x}),a", d16), 3),
+            6 => ("ld a,($ff00+c)".to_string(), 1),
+            _ => (format!("ld a,(${:04x})", d16), 3),
+        },
+        (_, 3) => match y {
+            0 => (format!("jp ${:04x}", d16), 3),
+            1 => (cb_prefixed(d8), 2),
+            6 => ("di".to_string(), 1),
+            7 => ("ei".to_string(), 1),
+            _ => (format!("db ${:02x}", opcode), 1),
+        },
+        (_, 4) if y < 4 => (format!("call {},${:04x}", CC[y], d16), 3),
+        (_, 5) if q == 0 => (format!("push {}", RP2[p]), 1),
+        (_, 5) if p == 0 => (format!("call ${:04x}", d16), 3),
+        (_, 6) => (format!("{}${:02x}", ALU[y], d8), 2),
+        (_, 7) => (format!("rst ${:02x}", y * 8), 1),
+        _ => (format!("db ${:02x}", opcode), 1), // unused opcodes
+    };
+    (text, length)
+}
+
+fn cb_prefixed(opcode: u8) -> String {
+    let (x, y, z) = (opcode >> 6, ((opcode >> 3) & 0x7) as usize, (opcode & 0x7) as usize);
+    match x {
+        0 => format!("{} {}", ROT[y], R[z]),
+        1 => format!("bit {},{}", y, R[z]),
+        2 => format!("res {},{}", y, R[z]),
+        _ => format!("set {},{}", y, R[z]),
+    }
+}
@@ -0,0 +1,98 @@
+use crate::state::{StateReader, StateWriter};
+const OAM_SIZE: u16 = 0xa0;
+
+pub struct Dma {
+    register: u8,     // last value written to 0xff46
+    source: u16,      // source address of the running transfer
+    index: u16,       // next byte of oam to be written
+    active: bool,     // transfer in progress, cpu bus is blocked
+    requested: Option<u16>, // written this instruction, starts after the next m-cycle
+    starting: Option<u16>,  // in the startup delay m-cycle
+    pub value: u8,    // byte currently on the bus
+}
+
+impl Dma {
+    pub fn new() -> Self {
+        Self {
+            register: 0xff,
+            source: 0,
+            index: 0,
+            active: false,
+            requested: None,
+            starting: None,
+            value: 0xff,
+        }
+    }
+
+    pub fn save_state(&self, state: &mut StateWriter) {
+        state.write_u8(self.register);
+        state.write_u16(self.source);
+        state.write_u16(self.index);
+        state.write_bool(self.active);
+        state.write_bool(self.requested.is_some());
+        state.write_u16(self.requested.unwrap_or(0));
+        state.write_bool(self.starting.is_some());
+        state.write_u16(self.starting.unwrap_or(0));
+        state.write_u8(self.value);
+    }
+
+    pub fn load_state(&mut self, state: &mut StateReader) -> Result<(), String> {
+        self.register = state.read_u8()?;
+        self.source = state.read_u16()?;
+        self.index = state.read_u16()?;
+        self.active = state.read_bool()?;
+        let requested = state.read_bool()?;
+        let requested_source = state.read_u16()?;
+        self.requested = if requested { Some(requested_source) } else { None };
+        let starting = state.read_bool()?;
+        let starting_source = state.read_u16()?;
+        self.starting = if starting { Some(starting_source) } else { None };
+        self.value = state.read_u8()?;
+        Ok(())
+    }
+
+    pub fn read_byte(&self) -> u8 {
+        self.register
+    }
+
+    pub fn write_byte(&mut self, value: u8) {
+        self.register = value;
+        // a restart doesn't stop the running transfer until the new one is set up
+        self.requested = Some((value as u16) << 8);
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.active
+    }
+
+    pub fn source(&self) -> u16 {
+        self.source
+    }
+
+    // the write lands on the last m-cycle of the instruction, so the startup
+    // delay is only counted from the next instruction onwards
+    pub fn end_instruction(&mut self) {
+        if let Some(source) = self.requested.take() {
+            self.starting = Some(source);
+        }
+    }
+
+    // advances one m-cycle, returns the source address and oam index to copy
+    pub fn step(&mut self) -> Option<(u16, u16)> {
+        let mut transfer = None;
+        if self.active {
+            transfer = Some((self.source + self.index, self.index));
+            self.index += 1;
+            if self.index == OAM_SIZE {
+                self.active = false;
+            }
+        }
+
+        if let Some(source) = self.starting.take() {
+            self.source = source;
+            self.index = 0;
+            self.active = true;
+        }
+        transfer
+    }
+}
@@ -1,17 +1,347 @@
 use crate::cpu::CPU;
+use crate::debug_window::DebugWindow;
+use crate::input::{FrameInput, InputSource};
+use crate::joypad;
+use crate::mmu::MMU;
+use crate::bindings::Hotkey;
 use crate::minifb_display::Display;
+use crate::movie::{Movie, MovieRecorder};
+use crate::pacer::FramePacer;
+use crate::palette::Palettes;
+use crate::ppu::{Layers, PPU};
+use crate::rewind::RewindBuffer;
+use crate::screenshot;
+use crate::serial::Link;
+use crate::sgb::{self, Sgb};
+use crate::state::{StateReader, StateWriter};
+use crate::video::VideoRecorder;
+use std::collections::VecDeque;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
 
+pub const CLOCKS_PER_FRAME: u32 = 70224;
+const STATE_MAGIC: &[u8; 8] = b"DMGSTATE";
+const STATE_VERSION: u8 = 2;
+const REWIND_INTERVAL: usize = 5; // frames between rewind snapshots
+const REWIND_SNAPSHOTS: usize = 720; // a minute of rewind
 
 pub struct Gameboy {
     cpu: CPU,
+    rom: Vec<u8>, // fresh consoles for resets and state loads are built from this
+    boot_rom: Option<Vec<u8>>,
+    save_name: PathBuf, // save files are this with an extension
+    battery_save: bool, // cartridge ram came from the save file and goes back to it
     display: Box<dyn Display>,
+    border: bool,
+    empty_border: Vec<u32>, // drawn around games without sgb support
+    power_on: bool, // nothing has run yet
+    movie: Option<usize>, // the movie being played among the inputs
+    recorder: Option<MovieRecorder>,
+    video: Option<VideoRecorder>,
+    debug_windows: Vec<DebugWindow>,
+    rewind: RewindBuffer,
+    inputs: Vec<(Box<dyn InputSource>, u8)>, // each source and the buttons it holds, 0 is pressed
+    pending_input: VecDeque<(u32, u8)>, // joypad changes still to come this frame
+    frame_input: FrameInput,            // joypad changes made so far this frame
+    frame_clocks: u32,
+    frame: Vec<u32>, // last frame shown
+    screenshot_scale: usize,
+    linked: bool,
+    trace: Option<BufWriter<File>>,
 }
 
 impl Gameboy {
-    pub fn new(rom_path: &str, display: Box<dyn Display>) -> Self {
-        Self {
-            cpu: CPU::new(rom_path),
+    pub fn new(rom_path: &str, display: Box<dyn Display>) -> Result<Self, String> {
+        let rom = fs::read(rom_path).map_err(|e| format!("{}: {}", rom_path, e))?;
+        Ok(Self {
+            cpu: CPU::new(rom.clone())?,
+            rom,
+            boot_rom: None,
+            save_name: Path::new(rom_path).with_extension(""),
+            battery_save: false,
             display,
+            border: false,
+            empty_border: vec![],
+            power_on: true,
+            movie: None,
+            recorder: None,
+            video: None,
+            debug_windows: vec![],
+            rewind: RewindBuffer::new(REWIND_INTERVAL, REWIND_SNAPSHOTS),
+            inputs: vec![],
+            pending_input: VecDeque::new(),
+            frame_input: vec![],
+            frame_clocks: 0,
+            frame: vec![0xffffff; crate::SCREEN_WIDTH * crate::SCREEN_HEIGHT],
+            screenshot_scale: 1,
+            linked: false,
+            trace: None,
+        })
+    }
+
+    pub fn run_boot_rom(&mut self, boot_rom: Vec<u8>) -> Result<(), String> {
+        if !self.power_on {
+            return Err("the boot rom can only run at power on".to_string());
+        }
+        self.cpu.run_boot_rom(boot_rom.clone())?;
+        self.boot_rom = Some(boot_rom);
+        Ok(())
+    }
+
+    // a powered on console with the boot rom, palettes and layers of this one.
+    // the link stays with this one until the new one is swapped in
+    fn new_cpu(&self) -> Result<CPU, String> {
+        let mut cpu = CPU::new(self.rom.clone())?;
+        if let Some(boot_rom) = &self.boot_rom {
+            cpu.run_boot_rom(boot_rom.clone())?;
+        }
+        cpu.mmu.ppu.palettes = self.cpu.mmu.ppu.palettes;
+        cpu.mmu.ppu.layers = self.cpu.mmu.ppu.layers;
+        Ok(cpu)
+    }
+
+    // starts the game over, cartridge ram, palettes and the link are kept
+    pub fn reset(&mut self) -> Result<(), String> {
+        let mut cpu = self.new_cpu()?;
+        if let Some(ram) = self.cpu.mmu.battery_ram() {
+            cpu.mmu.load_battery_ram(ram)?;
+        }
+        cpu.mmu.serial.connect(self.cpu.mmu.serial.disconnect());
+        self.cpu = cpu;
+        self.power_on = true;
+        self.rewind.clear();
+        Ok(())
+    }
+
+    // save files go next to the rom unless a directory is given
+    pub fn set_save_dir(&mut self, dir: &Path) {
+        if let Some(name) = self.save_name.file_name() {
+            self.save_name = dir.join(name);
+        }
+    }
+
+    fn save_file(&self, extension: &str) -> String {
+        format!("{}.{}", self.save_name.display(), extension)
+    }
+
+    // cartridge ram kept by a battery, a missing file is a fresh save
+    pub fn load_battery(&mut self) -> Result<(), String> {
+        let path = self.save_file("sav");
+        self.battery_save = true;
+        match fs::read(&path) {
+            Ok(ram) => self.cpu.mmu.load_battery_ram(&ram).map_err(|e| format!("{}: {}", path, e)),
+            Err(_) => Ok(()),
+        }
+    }
+
+    pub fn save_battery(&self) -> Result<(), String> {
+        if !self.battery_save {
+            return Ok(());
+        }
+        let path = self.save_file("sav");
+        match self.cpu.mmu.battery_ram() {
+            Some(ram) => fs::write(&path, ram).map_err(|e| format!("{}: {}", path, e)),
+            None => Ok(()),
+        }
+    }
+
+    // the last frame shown, with the border if it's drawn
+    pub fn screenshot(&self, path: &str) -> Result<(), String> {
+        let scale = self.screenshot_scale;
+        if self.border {
+            screenshot::save_png(path, &self.frame, sgb::BORDER_WIDTH, sgb::BORDER_HEIGHT, scale)
+        } else {
+            screenshot::save_png(path, &self.frame, crate::SCREEN_WIDTH, crate::SCREEN_HEIGHT, scale)
+        }
+    }
+
+    pub fn mmu(&self) -> &MMU {
+        &self.cpu.mmu
+    }
+
+    pub fn ppu(&self) -> &PPU {
+        &self.cpu.mmu.ppu
+    }
+
+    pub fn frame(&self) -> &[u32] {
+        &self.frame
+    }
+
+    // screenshots are the native size times this
+    pub fn set_screenshot_scale(&mut self, scale: usize) {
+        self.screenshot_scale = scale;
+    }
+
+    // writes the registers before every instruction
+    pub fn trace_to(&mut self, path: &str) -> Result<(), String> {
+        let file = File::create(path).map_err(|e| format!("{}: {}", path, e))?;
+        self.trace = Some(BufWriter::new(file));
+        Ok(())
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.display.is_open()
+    }
+
+    // draw the frame in a 256x224 sgb border, display must be sized to match
+    pub fn show_border(&mut self, border: bool) {
+        self.border = border;
+        if border && self.empty_border.is_empty() {
+            let screen = vec![0; crate::SCREEN_WIDTH * crate::SCREEN_HEIGHT];
+            Sgb::new().draw_border(&mut self.empty_border, &screen);
+        }
+    }
+
+    pub fn set_palettes(&mut self, palettes: Palettes) {
+        self.cpu.mmu.ppu.palettes = palettes;
+    }
+
+    // layers shown, the game doesn't see these
+    pub fn set_layers(&mut self, layers: Layers) {
+        self.cpu.mmu.ppu.layers = layers;
+    }
+
+    // redrawn after every frame until it's closed
+    pub fn add_debug_window(&mut self, window: DebugWindow) {
+        self.debug_windows.push(window);
+    }
+
+    fn update_debug_windows(&mut self) {
+        self.debug_windows.retain(|window| window.is_open());
+        for window in &mut self.debug_windows {
+            window.update(&mut self.cpu.mmu);
+        }
+    }
+
+    // sources are merged, a button is held while any of them holds it
+    pub fn add_input(&mut self, source: Box<dyn InputSource>) {
+        self.inputs.push((source, 0xff));
+    }
+
+    // plugs something into the link port
+    pub fn connect_link(&mut self, link: Box<dyn Link>) {
+        self.cpu.mmu.serial.connect(link);
+    }
+
+    // linked to another console, here or over tcp. states, resets and rewinding are turned off
+    pub fn set_linked(&mut self, linked: bool) {
+        self.linked = linked;
+    }
+
+    // save states start with a header tying them to the rom
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut state = StateWriter::new();
+        state.write_bytes(STATE_MAGIC);
+        state.write_u8(STATE_VERSION);
+        state.write_u32(self.cpu.mmu.rom_hash);
+        self.cpu.save_state(&mut state);
+        state.into_bytes()
+    }
+
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), String> {
+        let mut state = StateReader::new(data);
+        let mut magic = [0; 8];
+        state.read_bytes(&mut magic)?;
+        if &magic != STATE_MAGIC || state.read_u8()? != STATE_VERSION {
+            return Err("not a save state".to_string());
+        }
+        if state.read_u32()? != self.cpu.mmu.rom_hash {
+            return Err("save state is for a different rom".to_string());
+        }
+        // a state that fails part way leaves the running game as it was
+        let mut cpu = self.new_cpu()?;
+        cpu.load_state(&mut state)?;
+        cpu.mmu.serial.connect(self.cpu.mmu.serial.disconnect());
+        self.cpu = cpu;
+        self.power_on = false;
+        Ok(())
+    }
+
+    pub fn load_state_file(&mut self, path: &str) -> Result<(), String> {
+        let data = fs::read(path).map_err(|e| format!("{}: {}", path, e))?;
+        self.load_state(&data)?;
+        self.rewind.clear();
+        Ok(())
+    }
+
+    pub fn save_state_file(&self, path: &str) -> Result<(), String> {
+        fs::write(path, self.save_state()).map_err(|e| format!("{}: {}", path, e))
+    }
+
+    // records the buttons of every frame from here on, with a save state
+    // of the machine unless nothing has run yet
+    pub fn record_movie(&mut self, path: &str) -> Result<(), String> {
+        if self.power_on {
+            self.start_power_on_movie()?;
+        }
+        let start_state = if self.power_on { None } else { Some(self.save_state()) };
+        let recorder = MovieRecorder::create(path, self.cpu.mmu.rom_hash, start_state.as_deref())
+            .map_err(|e| format!("{}: {}", path, e))?;
+        self.recorder = Some(recorder);
+        Ok(())
+    }
+
+    // the movie's input replaces live input until it runs out
+    pub fn play_movie(&mut self, path: &str) -> Result<(), String> {
+        let mut movie = Movie::load(path)?;
+        if movie.rom_hash != self.cpu.mmu.rom_hash {
+            return Err(format!("{} was recorded with a different rom", path));
+        }
+        match &movie.start_state {
+            Some(state) => self.load_state(state)?,
+            None if !self.power_on => {
+                return Err(format!("{} starts at power on and has to be played first", path))
+            }
+            None => self.start_power_on_movie()?,
+        }
+        self.rewind.clear();
+        // it picks up from the buttons the start state has down
+        let buttons = self.cpu.mmu.joypad.buttons();
+        movie.buttons = buttons;
+        self.inputs.push((Box::new(movie), buttons));
+        self.movie = Some(self.inputs.len() - 1);
+        Ok(())
+    }
+
+    // power on movies only hold the rom hash, so they run without the boot rom and
+    // with blank cartridge ram whatever the save file holds. the save file is left alone
+    fn start_power_on_movie(&mut self) -> Result<(), String> {
+        if self.boot_rom.is_some() {
+            return Err("movies that start at power on can't use a boot rom".to_string());
+        }
+        if self.battery_save {
+            let mut cpu = self.new_cpu()?;
+            cpu.mmu.serial.connect(self.cpu.mmu.serial.disconnect());
+            self.cpu = cpu;
+            self.battery_save = false;
+        }
+        Ok(())
+    }
+
+    // every frame from here on, border included if it's drawn
+    pub fn record_video(&mut self, path: &str) -> Result<(), String> {
+        self.video = Some(if self.border {
+            VideoRecorder::create(path, sgb::BORDER_WIDTH, sgb::BORDER_HEIGHT)?
+        } else {
+            VideoRecorder::create(path, crate::SCREEN_WIDTH, crate::SCREEN_HEIGHT)?
+        });
+        Ok(())
+    }
+
+    pub fn finish_video(&mut self) -> Result<(), String> {
+        match self.video.take() {
+            Some(video) => video.finish(),
+            None => Ok(()),
+        }
+    }
+
+    fn capture_video_frame(&mut self) {
+        if let Some(video) = &mut self.video {
+            if let Err(e) = video.record_frame(&self.frame) {
+                println!("stopped recording video: {}", e);
+                self.video = None;
+            }
         }
     }
 
@@ -21,34 +351,259 @@ impl Gameboy {
         updated
     }
 
-    pub fn emulate(&mut self) {
-        let clocks_per_frame = 70224;
-        let mut clocks_this_update;
-        loop {
-            clocks_this_update = 0;
-            while clocks_this_update < clocks_per_frame {
-                clocks_this_update += self.cpu.do_cycle();
-                if self.ppu_updated() {
-                    self.update_disply();
+    // runs until the window is closed
+    pub fn emulate(&mut self, pacer: &mut FramePacer) {
+        while self.is_open() {
+            if pacer.frame_due() {
+                self.run_frame();
+            } else {
+                self.poll_display();
+            }
+            self.handle_hotkeys(pacer);
+            pacer.wait();
+        }
+    }
+
+    // keeps the window responsive while no frames are run
+    pub fn poll_display(&mut self) {
+        self.display.update();
+        self.update_debug_windows();
+    }
+
+    pub fn handle_hotkeys(&mut self, pacer: &mut FramePacer) {
+        let hotkeys: Vec<Hotkey> = self.inputs.iter_mut().flat_map(|(source, _)| source.hotkeys_pressed()).collect();
+        for hotkey in hotkeys {
+            match hotkey {
+                Hotkey::Pause => pacer.toggle_pause(),
+                Hotkey::FrameAdvance => pacer.advance_frame(),
+                Hotkey::SpeedUp => pacer.speed_up(),
+                Hotkey::SlowDown => pacer.slow_down(),
+                // they would only change one side of the link
+                Hotkey::SaveState | Hotkey::LoadState | Hotkey::Reset if self.linked => {
+                    println!("save states, resets and rewinding don't work while linked")
                 }
+                Hotkey::SaveState => {
+                    let path = self.save_file("state");
+                    match self.save_state_file(&path) {
+                        Ok(()) => println!("saved state to {}", path),
+                        Err(e) => println!("{}", e),
+                    }
+                }
+                // states and resets would desync a movie
+                Hotkey::LoadState if self.movie.is_none() && self.recorder.is_none() => {
+                    if let Err(e) = self.load_state_file(&self.save_file("state")) {
+                        println!("{}", e);
+                    }
+                }
+                Hotkey::Screenshot => {
+                    let path = self.save_file(&format!("{}.png", chrono::Local::now().format("%Y%m%d-%H%M%S")));
+                    match self.screenshot(&path) {
+                        Ok(()) => println!("saved screenshot to {}", path),
+                        Err(e) => println!("{}", e),
+                    }
+                }
+                Hotkey::Reset if self.movie.is_none() && self.recorder.is_none() => {
+                    if let Err(e) = self.reset() {
+                        println!("{}", e);
+                    }
+                }
+                Hotkey::ToggleBackground => {
+                    let layers = &mut self.cpu.mmu.ppu.layers;
+                    layers.background = !layers.background;
+                    println!("background {}", if layers.background { "on" } else { "off" });
+                }
+                Hotkey::ToggleWindow => {
+                    let layers = &mut self.cpu.mmu.ppu.layers;
+                    layers.window = !layers.window;
+                    println!("window {}", if layers.window { "on" } else { "off" });
+                }
+                Hotkey::ToggleSprites => {
+                    let layers = &mut self.cpu.mmu.ppu.layers;
+                    layers.sprites = !layers.sprites;
+                    println!("sprites {}", if layers.sprites { "on" } else { "off" });
+                }
+                Hotkey::TogglePriority => {
+                    let layers = &mut self.cpu.mmu.ppu.layers;
+                    layers.cgb_priority = !layers.cgb_priority;
+                    println!("cgb priority {}", if layers.cgb_priority { "on" } else { "off" });
+                }
+                _ => (),
             }
-            self.handle_input();
-            std::thread::sleep(std::time::Duration::from_millis(16));
         }
+        pacer.set_fast_forward(self.hotkey_held(Hotkey::FastForward));
     }
 
-    fn update_disply(&mut self){
-        let buffer = &self.cpu.mmu.ppu.screen_data;
-        self.display.update_with_buffer(buffer);
+    fn hotkey_held(&self, hotkey: Hotkey) -> bool {
+        self.inputs.iter().any(|(source, _)| source.hotkey_held(hotkey))
+    }
+
+    pub fn run_frames(&mut self, frames: u32) {
+        for _ in 0..frames {
+            self.run_frame();
+        }
+    }
+
+    pub fn run_frame(&mut self) {
+        // rewinding would desync a movie or the other side of a link
+        let rewind = self.movie.is_none() && self.recorder.is_none() && !self.linked;
+        if rewind && self.hotkey_held(Hotkey::Rewind) {
+            self.rewind_frame();
+            return;
+        }
+        self.begin_frame();
+        while self.frame_clocks < CLOCKS_PER_FRAME {
+            self.step();
+        }
+        self.end_frame();
+    }
+
+    // polls the input sources for the frame about to run, their changes are
+    // applied by step as the frame reaches them
+    pub fn begin_frame(&mut self) {
+        if self.rewind.snapshot_due() {
+            let state = self.save_state();
+            self.rewind.push_snapshot(state);
+        }
+        self.pending_input = self.poll_inputs().into();
+        self.frame_clocks = 0;
+    }
+
+    pub fn end_frame(&mut self) {
+        let input = std::mem::take(&mut self.frame_input);
+        if let Some(recorder) = &mut self.recorder {
+            if let Err(e) = recorder.record_frame(&input) {
+                println!("stopped recording: {}", e);
+                self.recorder = None;
+            }
+        }
+        self.rewind.record_frame(input);
+        self.capture_video_frame();
+        self.update_debug_windows();
+    }
+
+    // merges the events of every source into the joypad changes they make this frame
+    fn poll_inputs(&mut self) -> FrameInput {
+        if let Some(movie) = self.movie {
+            if self.inputs[movie].0.finished() {
+                println!("movie finished");
+                self.inputs.remove(movie);
+                self.movie = None;
+            }
+        }
+        let mut events = vec![];
+        for (i, (source, _)) in self.inputs.iter_mut().enumerate() {
+            events.extend(source.poll().into_iter().map(|event| (i, event)));
+        }
+        events.sort_by_key(|(_, event)| event.clock);
+
+        let mut input = vec![];
+        // after a rewind or a movie the joypad can differ from what is held
+        let mut buttons = self.held_buttons();
+        if buttons != self.cpu.mmu.joypad.buttons() {
+            input.push((0, buttons));
+        }
+        for (i, event) in events {
+            let mask = joypad::button_mask(event.button);
+            let held = &mut self.inputs[i].1;
+            if event.pressed {
+                *held &= !mask;
+            } else {
+                *held |= mask;
+            }
+            let held = self.held_buttons();
+            if held != buttons {
+                input.push((event.clock.min(CLOCKS_PER_FRAME - 1), held));
+                buttons = held;
+            }
+        }
+        input
+    }
+
+    // a movie replaces live input while it plays. the other sources are still
+    // followed, the joypad catches up with them once it's over
+    fn held_buttons(&self) -> u8 {
+        match self.movie {
+            Some(movie) => self.inputs[movie].1,
+            None => self.inputs.iter().fold(0xff, |buttons, (_, held)| buttons & held),
+        }
+    }
+
+    // sets the joypad at the first instruction boundary at or after each change's clock
+    fn apply_input(&mut self) {
+        while let Some(&(clock, buttons)) = self.pending_input.front() {
+            if clock > self.frame_clocks {
+                break;
+            }
+            self.pending_input.pop_front();
+            self.cpu.mmu.joypad.set_buttons(buttons);
+            self.frame_input.push((clock, buttons));
+        }
+    }
+
+    // goes back one frame by loading the snapshot before it and running the
+    // frames in between again with the input they had
+    fn rewind_frame(&mut self) {
+        if let Some((state, frames)) = self.rewind.step_back() {
+            if let Err(e) = self.load_state(&state) {
+                println!("rewind snapshot didn't load, {}", e);
+                self.rewind.clear();
+                return;
+            }
+            // only the last frame is drawn
+            let count = frames.len();
+            for (i, input) in frames.into_iter().enumerate() {
+                self.pending_input = input.into();
+                self.frame_clocks = 0;
+                while self.frame_clocks < CLOCKS_PER_FRAME {
+                    if i + 1 == count {
+                        self.step();
+                    } else {
+                        self.apply_input();
+                        self.frame_clocks += self.cpu.do_cycle();
+                    }
+                }
+                self.ppu_updated();
+            }
+            self.frame_input.clear();
+            self.capture_video_frame();
+        }
+        self.update_debug_windows();
+        // the buttons being held aren't rewound, the next frame catches the joypad up
+        self.poll_inputs();
     }
 
-    fn handle_input(&mut self){
-        for button in self.display.get_buttons_down() {
-            self.cpu.mmu.joypad.button_down(button);
+    // runs a single instruction, returns the clocks it took
+    pub fn step(&mut self) -> u32 {
+        self.power_on = false;
+        if let Some(trace) = &mut self.trace {
+            if let Err(e) = writeln!(trace, "{}", self.cpu.trace_line()) {
+                println!("stopped tracing: {}", e);
+                self.trace = None;
+            }
         }
+        self.apply_input();
+        let clocks = self.cpu.do_cycle();
+        self.frame_clocks += clocks;
+        if self.ppu_updated() {
+            self.update_disply();
+        }
+        clocks
+    }
 
-        for button in self.display.get_buttons_up() {
-            self.cpu.mmu.joypad.button_up(button);
+    fn update_disply(&mut self){
+        let buffer = &self.cpu.mmu.ppu.screen_data;
+        if self.border {
+            match &self.cpu.mmu.sgb {
+                Some(sgb) => sgb.draw_border(&mut self.frame, buffer),
+                None => {
+                    self.frame.clone_from(&self.empty_border);
+                    sgb::draw_screen(&mut self.frame, buffer);
+                }
+            }
+        } else {
+            self.frame.clear();
+            self.frame.extend_from_slice(buffer);
         }
+        self.display.update_with_buffer(&self.frame);
     }
 }
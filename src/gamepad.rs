@@ -0,0 +1,183 @@
+use crate::bindings;
+use crate::input::{self, InputEvent, InputSource};
+use crate::joypad::{self, Button};
+use gilrs::{Axis, EventType, Gilrs};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+
+const DEFAULT_DEADZONE: f32 = 0.5;
+
+// controllers through gilrs, every connected one can press buttons and
+// controllers can be plugged in and out while running
+pub struct Gamepad {
+    gilrs: Gilrs,
+    buttons: Vec<(gilrs::Button, Button)>,
+    deadzone: f32, // how far a stick has to move to press a direction
+    held: u8,      // buttons held at the last poll, 0 is pressed
+}
+
+impl Gamepad {
+    pub fn new(mapping: GamepadMapping) -> Result<Self, String> {
+        let gilrs = Gilrs::new().map_err(|e| format!("controllers are unavailable: {}", e))?;
+        Ok(Self {
+            gilrs,
+            buttons: mapping.buttons,
+            deadzone: mapping.deadzone,
+            held: 0xff,
+        })
+    }
+
+    // pressed buttons as a mask like the joypad's, 0 is pressed
+    fn read_buttons(&mut self) -> u8 {
+        // gilrs only updates its state as events are taken
+        while let Some(event) = self.gilrs.next_event() {
+            let name = self.gilrs.gamepad(event.id).name().to_string();
+            match event.event {
+                EventType::Connected => println!("controller connected: {}", name),
+                EventType::Disconnected => println!("controller disconnected: {}", name),
+                _ => (),
+            }
+        }
+
+        let mut state = 0xff;
+        for (_, gamepad) in self.gilrs.gamepads() {
+            for (gilrs_button, button) in &self.buttons {
+                if gamepad.is_pressed(*gilrs_button) {
+                    state &= !joypad::button_mask(*button);
+                }
+            }
+            // the left stick and d-pads that report as axes, up is positive
+            for (x_axis, y_axis) in [(Axis::LeftStickX, Axis::LeftStickY), (Axis::DPadX, Axis::DPadY)].iter() {
+                let (x, y) = (gamepad.value(*x_axis), gamepad.value(*y_axis));
+                if x < -self.deadzone {
+                    state &= !joypad::button_mask(Button::Left);
+                }
+                if x > self.deadzone {
+                    state &= !joypad::button_mask(Button::Right);
+                }
+                if y > self.deadzone {
+                    state &= !joypad::button_mask(Button::Up);
+                }
+                if y < -self.deadzone {
+                    state &= !joypad::button_mask(Button::Down);
+                }
+            }
+        }
+        state
+    }
+}
+
+// gilrs is read once a frame, so changes land at the start of a frame
+impl InputSource for Gamepad {
+    fn poll(&mut self) -> Vec<InputEvent> {
+        let held = self.read_buttons();
+        let events = input::mask_events(self.held, held);
+        self.held = held;
+        events
+    }
+}
+
+pub struct GamepadMapping {
+    buttons: Vec<(gilrs::Button, Button)>,
+    deadzone: f32,
+}
+
+impl GamepadMapping {
+    pub fn new() -> Self {
+        use gilrs::Button::*;
+        Self {
+            buttons: vec![
+                (DPadUp, Button::Up),
+                (DPadDown, Button::Down),
+                (DPadLeft, Button::Left),
+                (DPadRight, Button::Right),
+                (East, Button::A),
+                (South, Button::B),
+                (Start, Button::Start),
+                (Select, Button::Select),
+            ],
+            deadzone: DEFAULT_DEADZONE,
+        }
+    }
+
+    // the [gamepad] table of the config file, the defaults if there is none
+    pub fn load_config() -> Result<Self, String> {
+        match bindings::config_path() {
+            Some(path) if path.exists() => Self::from_file(&path.to_string_lossy()),
+            _ => Ok(Self::new()),
+        }
+    }
+
+    // controller buttons are named by position, anything left out keeps its defaults:
+    // [gamepad]
+    // deadzone = 0.3
+    // [gamepad.buttons]
+    // a = ["east", "north"]
+    pub fn from_file(path: &str) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+        let file: ConfigFile = toml::from_str(&text).map_err(|e| format!("{}: {}", path, e))?;
+        let mut mapping = Self::new();
+        if let Some(gamepad) = file.gamepad {
+            if let Some(deadzone) = gamepad.deadzone {
+                if !(0.0..1.0).contains(&deadzone) {
+                    return Err(format!("{}: deadzone should be between 0 and 1", path));
+                }
+                mapping.deadzone = deadzone;
+            }
+            for (name, gilrs_buttons) in gamepad.buttons {
+                let button = bindings::button_from_name(&name)
+                    .ok_or_else(|| format!("{}: unknown button {}", path, name))?;
+                let gilrs_buttons = gilrs_buttons
+                    .names()
+                    .iter()
+                    .map(|name| {
+                        gilrs_button_from_name(name)
+                            .ok_or_else(|| format!("{}: unknown controller button {}", path, name))
+                    })
+                    .collect::<Result<Vec<_>, String>>()?;
+                mapping.buttons.retain(|(_, bound)| *bound != button);
+                mapping
+                    .buttons
+                    .extend(gilrs_buttons.into_iter().map(|gilrs_button| (gilrs_button, button)));
+            }
+        }
+        Ok(mapping)
+    }
+}
+
+#[derive(Deserialize)]
+struct ConfigFile {
+    gamepad: Option<GamepadTable>,
+}
+
+#[derive(Deserialize)]
+struct GamepadTable {
+    deadzone: Option<f32>,
+    #[serde(default)]
+    buttons: HashMap<String, bindings::KeyList>,
+}
+
+fn gilrs_button_from_name(name: &str) -> Option<gilrs::Button> {
+    use gilrs::Button::*;
+    match name.to_lowercase().as_str() {
+        "south" => Some(South),
+        "east" => Some(East),
+        "north" => Some(North),
+        "west" => Some(West),
+        "left_bumper" => Some(LeftTrigger),
+        "left_trigger" => Some(LeftTrigger2),
+        "right_bumper" => Some(RightTrigger),
+        "right_trigger" => Some(RightTrigger2),
+        "select" => Some(Select),
+        "start" => Some(Start),
+        "mode" => Some(Mode),
+        "left_thumb" => Some(LeftThumb),
+        "right_thumb" => Some(RightThumb),
+        "dpad_up" => Some(DPadUp),
+        "dpad_down" => Some(DPadDown),
+        "dpad_left" => Some(DPadLeft),
+        "dpad_right" => Some(DPadRight),
+        _ => None,
+    }
+}
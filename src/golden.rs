@@ -0,0 +1,192 @@
+use crate::gameboy::Gameboy;
+use crate::headless_display::HeadlessDisplay;
+use crate::input::InputScript;
+use crate::screenshot;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// manifests are toml, paths are relative to the manifest. each test runs a rom
+// headless with an input script or movie and checks frames against a crc32 of
+// their pixels or a png:
+// [[test]]
+// name = "tetris title"
+// rom = "roms/tetris.gb"
+// input = "tetris.script"
+// [test.frames]
+// 60 = "crc32:8d3f0a2c"
+// 300 = "tetris-300.png"
+#[derive(Deserialize)]
+struct Manifest {
+    #[serde(default)]
+    test: Vec<Test>,
+}
+
+#[derive(Deserialize)]
+struct Test {
+    name: Option<String>,
+    rom: String,
+    input: Option<String>, // an input script
+    movie: Option<String>,
+    frames: BTreeMap<String, String>,
+}
+
+// runs every test in the manifest, mismatched frames are written to the diff directory
+// as the actual frame and one with the differing pixels marked. blessing writes the
+// actual frames over the expected pngs instead
+pub fn run(manifest_path: &str, diff_dir: Option<&str>, bless: bool) -> Result<(), String> {
+    let text = fs::read_to_string(manifest_path).map_err(|e| format!("{}: {}", manifest_path, e))?;
+    let manifest: Manifest = toml::from_str(&text).map_err(|e| format!("{}: {}", manifest_path, e))?;
+    let base = Path::new(manifest_path).parent().unwrap_or_else(|| Path::new("."));
+    let diff_dir = match diff_dir {
+        Some(dir) => PathBuf::from(dir),
+        None => base.join("diffs"),
+    };
+
+    let mut failed = 0;
+    let mut checked = 0;
+    for test in &manifest.test {
+        let name = test.name.clone().unwrap_or_else(|| test.rom.clone());
+        match run_test(test, &name, base, &diff_dir, bless) {
+            Ok(results) => {
+                for (frame, result) in results {
+                    checked += 1;
+                    match result {
+                        Ok(()) => println!("ok    {} frame {}", name, frame),
+                        Err(e) => {
+                            failed += 1;
+                            println!("FAIL  {} frame {}: {}", name, frame, e);
+                        }
+                    }
+                }
+            }
+            Err(e) => {
+                failed += 1;
+                println!("FAIL  {}: {}", name, e);
+            }
+        }
+    }
+    println!("{} frames checked, {} failed", checked, failed);
+    if failed > 0 {
+        Err(format!("{} golden frames failed", failed))
+    } else {
+        Ok(())
+    }
+}
+
+// each checked frame and whether it matched
+type FrameResults = Vec<(u32, Result<(), String>)>;
+
+// the result of each checked frame, an error if the test couldn't run at all
+fn run_test(
+    test: &Test,
+    name: &str,
+    base: &Path,
+    diff_dir: &Path,
+    bless: bool,
+) -> Result<FrameResults, String> {
+    let path = |file: &str| base.join(file).to_string_lossy().into_owned();
+    let mut expected = vec![];
+    for (frame, expectation) in &test.frames {
+        let frame: u32 = frame.parse().map_err(|_| format!("{} is not a frame number", frame))?;
+        expected.push((frame, expectation));
+    }
+    expected.sort_by_key(|(frame, _)| *frame);
+
+    let mut gameboy = Gameboy::new(&path(&test.rom), Box::new(HeadlessDisplay))?;
+    if let Some(input) = &test.input {
+        gameboy.add_input(Box::new(InputScript::load(&path(input))?));
+    }
+    if let Some(movie) = &test.movie {
+        gameboy.play_movie(&path(movie))?;
+    }
+
+    let mut results = vec![];
+    let mut frames_run = 0;
+    for (frame, expectation) in expected {
+        gameboy.run_frames(frame - frames_run);
+        frames_run = frame;
+        let actual = gameboy.frame();
+        let result = match expectation.strip_prefix("crc32:") {
+            Some(hash) => check_hash(actual, hash),
+            None if bless => {
+                screenshot::save_png(&path(expectation), actual, crate::SCREEN_WIDTH, crate::SCREEN_HEIGHT, 1)
+                    .map(|()| None)
+            }
+            None => check_png(actual, &path(expectation)),
+        };
+        // the actual frame and a diff against the png, if there is one to compare to
+        let result = result.and_then(|mismatch| match mismatch {
+            None => Ok(()),
+            Some(e) => {
+                let file_name = format!("{}-{}", name.replace(|c: char| !c.is_alphanumeric(), "_"), frame);
+                let written = write_diff(actual, &path(expectation), &diff_dir.join(file_name))?;
+                Err(written.iter().fold(e, |e, path| format!("{}\n      wrote {}", e, path)))
+            }
+        });
+        results.push((frame, result));
+    }
+    Ok(results)
+}
+
+fn frame_hash(frame: &[u32]) -> u32 {
+    let bytes: Vec<u8> = frame.iter().flat_map(|pixel| pixel.to_be_bytes()[1..].to_vec()).collect();
+    crc32fast::hash(&bytes)
+}
+
+// a mismatch is reported with the hash to paste into the manifest
+fn check_hash(frame: &[u32], expected: &str) -> Result<Option<String>, String> {
+    let expected = u32::from_str_radix(expected, 16).map_err(|_| format!("{} is not a crc32", expected))?;
+    let actual = frame_hash(frame);
+    if actual == expected {
+        Ok(None)
+    } else {
+        Ok(Some(format!("expected crc32:{:08x}, got crc32:{:08x}", expected, actual)))
+    }
+}
+
+fn check_png(frame: &[u32], path: &str) -> Result<Option<String>, String> {
+    let (expected, width, height) = screenshot::load_png(path)?;
+    if (width, height) != (crate::SCREEN_WIDTH, crate::SCREEN_HEIGHT) {
+        return Ok(Some(format!("{} is {}x{}, frames are 160x144", path, width, height)));
+    }
+    let differences = expected.iter().zip(frame).filter(|(expected, actual)| expected != actual).count();
+    if differences == 0 {
+        Ok(None)
+    } else {
+        Ok(Some(format!("{} pixels differ from {}", differences, path)))
+    }
+}
+
+// <name>-actual.png, and <name>-diff.png with differing pixels in red over a faded
+// copy of the expected frame when there is a png to compare against. returns the files written
+fn write_diff(actual: &[u32], expected_path: &str, diff_path: &Path) -> Result<Vec<String>, String> {
+    fs::create_dir_all(diff_path.parent().unwrap_or_else(|| Path::new(".")))
+        .map_err(|e| format!("{}: {}", diff_path.display(), e))?;
+    let (width, height) = (crate::SCREEN_WIDTH, crate::SCREEN_HEIGHT);
+    let actual_path = format!("{}-actual.png", diff_path.display());
+    screenshot::save_png(&actual_path, actual, width, height, 1)?;
+
+    let expected = match screenshot::load_png(expected_path) {
+        Ok((expected, w, h)) if (w, h) == (width, height) => expected,
+        _ => return Ok(vec![actual_path]),
+    };
+    let diff: Vec<u32> = expected
+        .iter()
+        .zip(actual)
+        .map(|(expected, actual)| {
+            if expected == actual {
+                // faded towards white
+                let [_, r, g, b] = expected.to_be_bytes();
+                let fade = |channel: u8| 0xc0 + channel / 4;
+                u32::from_be_bytes([0, fade(r), fade(g), fade(b)])
+            } else {
+                0xff0000
+            }
+        })
+        .collect();
+    let diff_path = format!("{}-diff.png", diff_path.display());
+    screenshot::save_png(&diff_path, &diff, width, height, 1)?;
+    Ok(vec![actual_path, diff_path])
+}
@@ -0,0 +1,91 @@
+use crate::state::{StateReader, StateWriter};
+
+pub struct Hdma {
+    source: u16,
+    destination: u16, // offset into vram
+    blocks: u8,       // 0x10 byte blocks left to copy
+    hblank_mode: bool,
+    active: bool,
+}
+
+impl Hdma {
+    pub fn new() -> Self {
+        Self {
+            source: 0,
+            destination: 0,
+            blocks: 0,
+            hblank_mode: false,
+            active: false,
+        }
+    }
+
+    pub fn save_state(&self, state: &mut StateWriter) {
+        state.write_u16(self.source);
+        state.write_u16(self.destination);
+        state.write_u8(self.blocks);
+        state.write_bool(self.hblank_mode);
+        state.write_bool(self.active);
+    }
+
+    pub fn load_state(&mut self, state: &mut StateReader) -> Result<(), String> {
+        self.source = state.read_u16()?;
+        self.destination = state.read_u16()?;
+        self.blocks = state.read_u8()?;
+        self.hblank_mode = state.read_bool()?;
+        self.active = state.read_bool()?;
+        Ok(())
+    }
+
+    pub fn read_byte(&self, address: u16) -> u8 {
+        match address {
+            0xff51..=0xff54 => 0xff, // write only
+            0xff55 => {
+                if self.active {
+                    self.blocks - 1
+                } else {
+                    0xff
+                }
+            }
+            n => unreachable!("address {:#x} is not handled by hdma", n),
+        }
+    }
+
+    pub fn write_byte(&mut self, address: u16, value: u8) {
+        match address {
+            0xff51 => self.source = (self.source & 0xff) | ((value as u16) << 8),
+            0xff52 => self.source = (self.source & 0xff00) | (value & 0xf0) as u16,
+            0xff53 => self.destination = (self.destination & 0xff) | (((value & 0x1f) as u16) << 8),
+            0xff54 => self.destination = (self.destination & 0x1f00) | (value & 0xf0) as u16,
+            0xff55 => {
+                // writing bit 7 clear during a hblank transfer cancels it
+                if self.active && self.hblank_mode && value & 0x80 == 0 {
+                    self.active = false;
+                    return;
+                }
+                self.blocks = (value & 0x7f) + 1;
+                self.hblank_mode = value & 0x80 == 0x80;
+                self.active = true;
+            }
+            n => unreachable!("address {:#x} is not handled by hdma", n),
+        }
+    }
+
+    // general purpose transfers copy everything at once
+    pub fn general_pending(&self) -> bool {
+        self.active && !self.hblank_mode
+    }
+
+    pub fn hblank_pending(&self) -> bool {
+        self.active && self.hblank_mode
+    }
+
+    // returns the source and vram destination of the next block
+    pub fn next_block(&mut self) -> (u16, u16) {
+        let block = (self.source, 0x8000 | self.destination);
+        self.source = self.source.wrapping_add(0x10);
+        self.destination = (self.destination + 0x10) & 0x1fff;
+        self.blocks -= 1;
+        self.active = self.blocks > 0;
+        block
+    }
+}
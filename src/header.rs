@@ -0,0 +1,134 @@
+use std::fmt;
+
+// the cartridge header at 0x0100-0x014f
+pub struct Header {
+    title: String,
+    cgb: u8,
+    sgb: bool,
+    cartridge_type: u8,
+    rom_size: u8,
+    ram_size: u8,
+    version: u8,
+    header_checksum: u8,
+    computed_header_checksum: u8,
+    global_checksum: u16,
+    computed_global_checksum: u16,
+}
+
+impl Header {
+    pub fn parse(rom: &[u8]) -> Result<Self, String> {
+        if rom.len() < 0x150 {
+            return Err("rom is too small to have a header".to_string());
+        }
+        // the title shrank to 11 bytes on cgb to make room for the manufacturer code
+        let title_end = if rom[0x143] & 0x80 == 0x80 { 0x13f } else { 0x144 };
+        let title = rom[0x134..title_end]
+            .iter()
+            .take_while(|byte| **byte != 0)
+            .map(|byte| *byte as char)
+            .collect();
+        let computed_header_checksum = rom[0x134..0x14d]
+            .iter()
+            .fold(0u8, |sum, byte| sum.wrapping_sub(*byte).wrapping_sub(1));
+        let computed_global_checksum = rom
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| *i != 0x14e && *i != 0x14f)
+            .fold(0u16, |sum, (_, byte)| sum.wrapping_add(*byte as u16));
+        Ok(Self {
+            title,
+            cgb: rom[0x143],
+            sgb: rom[0x146] == 0x03 && rom[0x14b] == 0x33,
+            cartridge_type: rom[0x147],
+            rom_size: rom[0x148],
+            ram_size: rom[0x149],
+            version: rom[0x14c],
+            header_checksum: rom[0x14d],
+            computed_header_checksum,
+            global_checksum: (rom[0x14e] as u16) << 8 | rom[0x14f] as u16,
+            computed_global_checksum,
+        })
+    }
+
+    fn cartridge_name(&self) -> &'static str {
+        match self.cartridge_type {
+            0x00 => "ROM ONLY",
+            0x01 => "MBC1",
+            0x02 => "MBC1+RAM",
+            0x03 => "MBC1+RAM+BATTERY",
+            0x05 => "MBC2",
+            0x06 => "MBC2+BATTERY",
+            0x08 => "ROM+RAM",
+            0x09 => "ROM+RAM+BATTERY",
+            0x0b => "MMM01",
+            0x0c => "MMM01+RAM",
+            0x0d => "MMM01+RAM+BATTERY",
+            0x0f => "MBC3+TIMER+BATTERY",
+            0x10 => "MBC3+TIMER+RAM+BATTERY",
+            0x11 => "MBC3",
+            0x12 => "MBC3+RAM",
+            0x13 => "MBC3+RAM+BATTERY",
+            0x19 => "MBC5",
+            0x1a => "MBC5+RAM",
+            0x1b => "MBC5+RAM+BATTERY",
+            0x1c => "MBC5+RUMBLE",
+            0x1d => "MBC5+RUMBLE+RAM",
+            0x1e => "MBC5+RUMBLE+RAM+BATTERY",
+            0x20 => "MBC6",
+            0x22 => "MBC7+SENSOR+RUMBLE+RAM+BATTERY",
+            0xfc => "POCKET CAMERA",
+            0xfd => "BANDAI TAMA5",
+            0xfe => "HuC3",
+            0xff => "HuC1+RAM+BATTERY",
+            _ => "unknown",
+        }
+    }
+
+    fn ram_kilobytes(&self) -> Option<u32> {
+        match self.ram_size {
+            0 => Some(0),
+            1 => Some(2),
+            2 => Some(8),
+            3 => Some(32),
+            4 => Some(128),
+            5 => Some(64),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Header {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let ok = |valid: bool| if valid { "ok" } else { "bad" };
+        writeln!(f, "title            {}", self.title)?;
+        let mode = match self.cgb {
+            0xc0 => "cgb only",
+            0x80 => "cgb enhanced",
+            _ => "dmg",
+        };
+        writeln!(f, "mode             {}{}", mode, if self.sgb { ", sgb" } else { "" })?;
+        writeln!(f, "cartridge        {:#04x} {}", self.cartridge_type, self.cartridge_name())?;
+        if self.rom_size <= 8 {
+            writeln!(f, "rom size         {} kb", 32 << self.rom_size)?;
+        } else {
+            writeln!(f, "rom size         unknown {:#04x}", self.rom_size)?;
+        }
+        match self.ram_kilobytes() {
+            Some(kilobytes) => writeln!(f, "ram size         {} kb", kilobytes)?,
+            None => writeln!(f, "ram size         unknown {:#04x}", self.ram_size)?,
+        }
+        writeln!(f, "version          {}", self.version)?;
+        writeln!(
+            f,
+            "header checksum  {:#04x} {}",
+            self.header_checksum,
+            ok(self.header_checksum == self.computed_header_checksum)
+        )?;
+        write!(
+            f,
+            "global checksum  {:#06x} {}",
+            self.global_checksum,
+            ok(self.global_checksum == self.computed_global_checksum)
+        )
+    }
+}
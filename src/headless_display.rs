@@ -0,0 +1,14 @@
+use crate::minifb_display::Display;
+
+// no window, for running frames in the background
+pub struct HeadlessDisplay;
+
+impl Display for HeadlessDisplay {
+    fn update_with_buffer(&mut self, _buffer: &[u32]) {}
+
+    fn update(&mut self) {}
+
+    fn is_open(&self) -> bool {
+        true
+    }
+}
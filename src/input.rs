@@ -0,0 +1,130 @@
+use crate::bindings::{self, Hotkey};
+use crate::gameboy::CLOCKS_PER_FRAME;
+use crate::joypad::{self, Button};
+use std::fs;
+
+// a button pressed or released, clock counts from the start of the frame it happens in
+#[derive(Debug, Clone, Copy)]
+pub struct InputEvent {
+    pub clock: u32,
+    pub button: Button,
+    pub pressed: bool,
+}
+
+// the joypad states a frame went through and the clocks they were set at,
+// what movies and the rewind buffer keep to replay a frame exactly
+pub type FrameInput = Vec<(u32, u8)>;
+
+// anything that presses buttons, polled once at the start of every frame for the
+// events of that frame. a button is held while any source holds it. scripts and
+// movies place their events anywhere in the frame, but the keyboard and gamepad
+// are only read between frames so everything they do lands on clock 0
+pub trait InputSource {
+    fn poll(&mut self) -> Vec<InputEvent>;
+
+    // sources that run out, like movies, are dropped once they have
+    fn finished(&self) -> bool {
+        false
+    }
+
+    // emulator controls, only sources with keys have them
+    fn hotkeys_pressed(&mut self) -> Vec<Hotkey> {
+        vec![]
+    }
+
+    fn hotkey_held(&self, _hotkey: Hotkey) -> bool {
+        false
+    }
+}
+
+// the presses and releases that take the buttons held from one mask to
+// another, both with 0 for pressed. they all land at the start of the frame
+pub fn mask_events(old: u8, new: u8) -> Vec<InputEvent> {
+    let buttons = [
+        Button::Down,
+        Button::Up,
+        Button::Left,
+        Button::Right,
+        Button::Start,
+        Button::Select,
+        Button::B,
+        Button::A,
+    ];
+    let changed = old ^ new;
+    buttons
+        .iter()
+        .filter(|button| changed & joypad::button_mask(**button) != 0)
+        .map(|button| InputEvent {
+            clock: 0,
+            button: *button,
+            pressed: new & joypad::button_mask(*button) == 0,
+        })
+        .collect()
+}
+
+// buttons pressed at given frames, read from a text file with one event a line:
+// # frame[+clocks] button down|up
+// 60 start down
+// 62 start up
+// 120+35112 a down
+pub struct InputScript {
+    events: Vec<(u32, InputEvent)>, // frame and event, in order
+    frame: u32,
+    next: usize,
+}
+
+impl InputScript {
+    pub fn load(path: &str) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+        let mut events = vec![];
+        for (number, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            let event = parse_line(line).map_err(|e| format!("{} line {}: {}", path, number + 1, e))?;
+            events.push(event);
+        }
+        // events on the same frame and clock keep the order they were written in
+        events.sort_by_key(|(frame, event)| (*frame, event.clock));
+        Ok(Self { events, frame: 0, next: 0 })
+    }
+}
+
+fn parse_line(line: &str) -> Result<(u32, InputEvent), String> {
+    let parts: Vec<&str> = line.split_whitespace().collect();
+    if parts.len() != 3 {
+        return Err(format!("{} should look like frame button down|up", line));
+    }
+    let (frame, clock) = match parts[0].find('+') {
+        Some(index) => (&parts[0][..index], &parts[0][index + 1..]),
+        None => (parts[0], "0"),
+    };
+    let frame = frame.parse().map_err(|_| format!("{} is not a frame number", frame))?;
+    let clock: u32 = clock.parse().map_err(|_| format!("{} is not a clock", clock))?;
+    if clock >= CLOCKS_PER_FRAME {
+        return Err(format!("clock {} is past the end of the frame", clock));
+    }
+    let button = bindings::button_from_name(parts[1]).ok_or_else(|| format!("unknown button {}", parts[1]))?;
+    let pressed = match parts[2] {
+        "down" => true,
+        "up" => false,
+        state => return Err(format!("{} should be down or up", state)),
+    };
+    Ok((frame, InputEvent { clock, button, pressed }))
+}
+
+impl InputSource for InputScript {
+    fn poll(&mut self) -> Vec<InputEvent> {
+        let mut events = vec![];
+        while let Some((frame, event)) = self.events.get(self.next) {
+            if *frame > self.frame {
+                break;
+            }
+            events.push(*event);
+            self.next += 1;
+        }
+        self.frame += 1;
+        events
+    }
+}
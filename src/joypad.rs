@@ -1,5 +1,6 @@
+use crate::state::{StateReader, StateWriter};
 use crate::utills::check_bit;
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Button {
     Down,
     Up,
@@ -11,8 +12,8 @@ pub enum Button {
     A,
 }
 pub struct Joypad {
-    button_state: u8,
-    data: u8,
+    button_state: u8, // directions in the high nibble, buttons in the low, 0 is pressed
+    select: u8,       // p14 and p15 select lines, low selects a group
     pub interrupt: u8,
 }
 
@@ -20,62 +21,73 @@ impl Joypad {
     pub fn new() -> Self {
         Self {
             button_state: 0xff,
-            data: 0xff,
+            select: 0x30,
             interrupt: 0,
         }
     }
 
     pub fn read_byte(&self) -> u8 {
-        self.data
+        0xc0 | self.select | self.input_lines() // bits 6 and 7 are unused and read as 1
     }
 
     pub fn write_byte(&mut self, value: u8) {
-        self.data = (self.data & 0xcf) | (value & 0x30);
+        let old_lines = self.input_lines();
+        self.select = value & 0x30;
+        self.update(old_lines);
     }
 
-    pub fn button_up(&mut self, button: Button) {
-        match button {
-            Button::Down => self.button_state |= 0x80,
-            Button::Up => self.button_state |= 0x40,
-            Button::Left => self.button_state |= 0x20,
-            Button::Right => self.button_state |= 0x10,
-            Button::Start => self.button_state |= 0x8,
-            Button::Select => self.button_state |= 0x4,
-            Button::B => self.button_state |= 0x2,
-            Button::A => self.button_state |= 0x1,
-        }
-        self.update();
+    // pressed buttons as a bit mask, 0 is pressed
+    pub fn buttons(&self) -> u8 {
+        self.button_state
+    }
 
+    pub fn set_buttons(&mut self, button_state: u8) {
+        let old_lines = self.input_lines();
+        self.button_state = button_state;
+        self.update(old_lines);
     }
 
-    pub fn button_down(&mut self, button: Button) {
-        match button {
-            Button::Down => self.button_state &= 0x7f,
-            Button::Up =>  self.button_state &= 0xbf,
-            Button::Left => self.button_state &= 0xdf,
-            Button::Right => self.button_state &= 0xef,
-            Button::Start => self.button_state &= 0xf7,
-            Button::Select => self.button_state &= 0xfb,
-            Button::B => self.button_state &= 0xfd,
-            Button::A => self.button_state &= 0xfe,
-        }
-        self.update();
+    pub fn save_state(&self, state: &mut StateWriter) {
+        state.write_u8(self.button_state);
+        state.write_u8(self.select);
+    }
+
+    pub fn load_state(&mut self, state: &mut StateReader) -> Result<(), String> {
+        self.button_state = state.read_u8()?;
+        self.select = state.read_u8()?;
+        Ok(())
     }
 
-    fn update(&mut self) {
-        let old_button_data = self.data & 0xf; // get old state
-        let mut new_button_data = 0xf; // new state no buttons pressed
-        println!("{:b}", self.data);
-        if check_bit(self.data, 4) {
-            new_button_data &= self.button_state >> 4;
-        } else if check_bit(self.data, 5) {
-            new_button_data &= self.button_state;
+    // lines of both groups are and-ed together when both are selected
+    fn input_lines(&self) -> u8 {
+        let mut lines = 0xf;
+        if !check_bit(self.select, 4) {
+            lines &= self.button_state >> 4;
         }
+        if !check_bit(self.select, 5) {
+            lines &= self.button_state & 0xf;
+        }
+        lines
+    }
 
-        if new_button_data != 0xf && old_button_data == 0xf {
+    // any selected line going from high to low requests an interrupt
+    fn update(&mut self, old_lines: u8) {
+        if old_lines & !self.input_lines() & 0xf != 0 {
             self.interrupt = 0x10;
         }
+    }
+}
 
-        self.data = (self.data & 0xf0) | new_button_data;
+// the button's bit in the button state
+pub fn button_mask(button: Button) -> u8 {
+    match button {
+        Button::Down => 0x80,
+        Button::Up => 0x40,
+        Button::Left => 0x20,
+        Button::Right => 0x10,
+        Button::Start => 0x8,
+        Button::Select => 0x4,
+        Button::B => 0x2,
+        Button::A => 0x1,
     }
 }
@@ -1,24 +1,350 @@
+mod bindings;
+mod cable;
+mod cli;
 mod cpu;
+mod debug_window;
+mod disasm;
+mod dma;
+mod font;
+#[cfg(feature = "gamepad")]
+mod gamepad;
 mod gameboy;
+mod golden;
+mod hdma;
+mod header;
+mod headless_display;
+mod input;
 mod joypad;
 mod mbc;
+mod memory_viewer;
 mod mmu;
+mod movie;
+mod oam_viewer;
+mod pacer;
+mod palette;
 mod ppu;
+mod printer;
+mod rewind;
+mod screenshot;
 mod serial;
+mod sgb;
+mod state;
+mod tcp_link;
+mod tile_viewer;
+mod tilemap_viewer;
 mod timer;
 mod utills;
+mod video;
+mod watch;
 mod minifb_display;
 const SCREEN_WIDTH: usize = 160;
 const SCREEN_HEIGHT: usize = 144;
+const TEST_SETTLE_FRAMES: u32 = 30;
+use cli::{Command, RunOptions};
+use debug_window::DebugView;
 use gameboy::Gameboy;
+use bindings::KeyBindings;
+use minifb_display::MinifbDisplay;
+use std::cell::RefCell;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
 
 fn main() {
-    let args: Vec<String> = std::env::args().collect();
-    if args.len() < 2 {
-        panic!("Missing file path")
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let command = match cli::parse(&args) {
+        Ok(command) => command,
+        Err(e) => {
+            eprintln!("error: {}\n\n{}", e, cli::USAGE);
+            std::process::exit(2);
+        }
+    };
+    let result = match command {
+        Command::Run(options) => run(options),
+        Command::Info { rom } => info(&rom),
+        Command::Disasm { rom, start, count } => disasm(&rom, start, count),
+        Command::Test { rom, frames } => test(&rom, frames),
+        Command::Golden { manifest, diff_dir, bless } => golden::run(&manifest, diff_dir.as_deref(), bless),
+        Command::Help => {
+            println!("{}", cli::USAGE);
+            Ok(())
+        }
+    };
+    if let Err(e) = result {
+        eprintln!("error: {}", e);
+        std::process::exit(1);
     }
-    let display = minifb_display::MinifbDisplay::new(SCREEN_WIDTH, SCREEN_HEIGHT);
-    let mut gameboy = Gameboy::new(&args[1], display);
-    gameboy.emulate();
+}
 
-}
\ No newline at end of file
+fn run(options: Box<RunOptions>) -> Result<(), String> {
+    let mut key_bindings = match &options.config {
+        Some(path) => KeyBindings::from_file(path)?,
+        None => KeyBindings::load_config()?,
+    };
+    if let Some(keys) = &options.keys {
+        key_bindings.parse(keys)?;
+    }
+    let (width, height) = if options.border {
+        (sgb::BORDER_WIDTH, sgb::BORDER_HEIGHT)
+    } else {
+        (SCREEN_WIDTH, SCREEN_HEIGHT)
+    };
+    let boot_rom = match &options.boot_rom {
+        Some(path) => Some(std::fs::read(path).map_err(|e| format!("{}: {}", path, e))?),
+        None => None,
+    };
+    // battery saves, save states and screenshots sit next to the rom unless a save directory is given
+    let save_dir = |rom: &str| match &options.save_dir {
+        Some(save_dir) => PathBuf::from(save_dir),
+        None => Path::new(rom).parent().unwrap_or_else(|| Path::new(".")).to_path_buf(),
+    };
+    // a window with the keyboard as input, or nothing when running headless.
+    // a linked second console is set up the same way
+    let new_gameboy = |rom: &str, width, height| -> Result<Gameboy, String> {
+        let mut gameboy = match options.frames {
+            Some(_) => Gameboy::new(rom, Box::new(headless_display::HeadlessDisplay))?,
+            None => {
+                let display = MinifbDisplay::new(width, height, options.scale)?;
+                let keyboard = display.keyboard(key_bindings.clone());
+                let mut gameboy = Gameboy::new(rom, display)?;
+                gameboy.add_input(Box::new(keyboard));
+                gameboy
+            }
+        };
+        gameboy.set_layers(options.layers);
+        if let Some(boot_rom) = &boot_rom {
+            gameboy.run_boot_rom(boot_rom.clone())?;
+        }
+        gameboy.set_save_dir(&save_dir(rom));
+        gameboy.load_battery()?;
+        Ok(gameboy)
+    };
+    let mut gameboy = new_gameboy(&options.rom, width, height)?;
+    gameboy.show_border(options.border);
+    gameboy.set_screenshot_scale(options.screenshot_scale);
+    #[cfg(feature = "gamepad")]
+    {
+        if options.frames.is_none() {
+            let mapping = match &options.config {
+                Some(path) => gamepad::GamepadMapping::from_file(path)?,
+                None => gamepad::GamepadMapping::load_config()?,
+            };
+            // the keyboard still works without controllers
+            match gamepad::Gamepad::new(mapping) {
+                Ok(gamepad) => gameboy.add_input(Box::new(gamepad)),
+                Err(e) => println!("{}", e),
+            }
+        }
+    }
+    if let Some(path) = &options.input_script {
+        gameboy.add_input(Box::new(input::InputScript::load(path)?));
+    }
+
+    let mut linked_gameboy = None;
+    if let Some(link) = &options.link {
+        match link.as_str() {
+            "stdout" => gameboy.connect_link(Box::new(serial::Stdout)),
+            "loopback" => gameboy.connect_link(Box::new(serial::Loopback)),
+            // printouts are saved in the save directory or the one given
+            "printer" => {
+                let save_dir = save_dir(&options.rom);
+                gameboy.connect_link(Box::new(printer::Printer::new(&save_dir.to_string_lossy())))
+            }
+            link if link.starts_with("printer:") => {
+                gameboy.connect_link(Box::new(printer::Printer::new(&link["printer:".len()..])))
+            }
+            // another emulator over tcp, one side listens and the other connects
+            link if link.starts_with("listen:") => {
+                let link = tcp_link::TcpLink::listen(&link["listen:".len()..]).map_err(|e| e.to_string())?;
+                gameboy.connect_link(Box::new(link));
+                gameboy.set_linked(true);
+            }
+            link if link.starts_with("connect:") => {
+                let link = tcp_link::TcpLink::connect(&link["connect:".len()..]).map_err(|e| e.to_string())?;
+                gameboy.connect_link(Box::new(link));
+                gameboy.set_linked(true);
+            }
+            // a second console in this process, in its own window
+            link if link.starts_with("local:") => {
+                linked_gameboy = Some(new_gameboy(&link["local:".len()..], SCREEN_WIDTH, SCREEN_HEIGHT)?);
+            }
+            link => {
+                return Err(format!(
+                    "unknown link {}, use stdout, loopback, printer, listen:address, connect:address or local:rom path",
+                    link
+                ))
+            }
+        }
+    }
+    // a preset name or the path of a palette file
+    if let Some(palette) = &options.palette {
+        let palettes = palette::Palettes::load(palette)?;
+        gameboy.set_palettes(palettes);
+        if let Some(linked_gameboy) = &mut linked_gameboy {
+            linked_gameboy.set_palettes(palettes);
+        }
+    }
+    // a save state to start from, movies recorded afterwards start from it too
+    if let Some(path) = &options.load_state {
+        gameboy.load_state_file(path)?;
+    }
+    if let Some(path) = &options.play {
+        gameboy.play_movie(path)?;
+    }
+    if let Some(path) = &options.record {
+        gameboy.record_movie(path)?;
+    }
+    if let Some(path) = &options.trace {
+        gameboy.trace_to(path)?;
+    }
+    if let Some(path) = &options.video {
+        gameboy.record_video(path)?;
+    }
+    if options.tile_viewer && options.frames.is_none() {
+        let viewer = tile_viewer::TileViewer::new(options.tile_palette, gameboy.ppu().is_cgb());
+        gameboy.add_debug_window(debug_window::DebugWindow::new(Box::new(viewer))?);
+    }
+    if options.tilemap_viewer && options.frames.is_none() {
+        gameboy.add_debug_window(debug_window::DebugWindow::new(Box::new(tilemap_viewer::TilemapViewer))?);
+    }
+    if options.oam_viewer && options.frames.is_none() {
+        gameboy.add_debug_window(debug_window::DebugWindow::new(Box::new(oam_viewer::OamViewer))?);
+    }
+    let mut watch = match &options.watch {
+        Some(path) => Some(watch::WatchList::load(path)?),
+        None => None,
+    };
+    // the watch list goes in the memory viewer, headless runs print it instead
+    if (options.memory_viewer || watch.is_some()) && options.frames.is_none() {
+        let viewer = memory_viewer::MemoryViewer::new(0xc000, watch.take());
+        gameboy.add_debug_window(debug_window::DebugWindow::new(Box::new(viewer))?);
+    }
+
+    // frames count from 1, the first frame run
+    let mut after_frame = |gameboy: &Gameboy, frame| {
+        if let Some(watch) = &mut watch {
+            for (watch, value) in watch.changes(gameboy.mmu()) {
+                println!("frame {} {} {:04x} = {}", frame, watch.label, watch.address, watch.format(value));
+            }
+        }
+        match (&options.screenshot, options.screenshot_at) {
+            (Some(path), Some(at)) if at == frame => gameboy.screenshot(path),
+            _ => Ok(()),
+        }
+    };
+    let mut pacer = pacer::FramePacer::new(options.speed);
+    let mut gameboy = match linked_gameboy {
+        Some(linked_gameboy) => {
+            let mut linked = cable::LinkedGameboys::new(gameboy, linked_gameboy);
+            match options.frames {
+                Some(frames) => {
+                    for frame in 1..=frames {
+                        linked.run_frame();
+                        after_frame(&linked.left, frame)?;
+                    }
+                }
+                None => linked.emulate(&mut pacer),
+            }
+            linked.right.save_battery()?;
+            linked.left
+        }
+        None => {
+            match options.frames {
+                Some(frames) => {
+                    for frame in 1..=frames {
+                        gameboy.run_frame();
+                        after_frame(&gameboy, frame)?;
+                    }
+                }
+                None => gameboy.emulate(&mut pacer),
+            }
+            gameboy
+        }
+    };
+
+    if let (Some(path), None) = (&options.screenshot, options.screenshot_at) {
+        gameboy.screenshot(path)?;
+    }
+    if let Some(path) = &options.dump_tiles {
+        let viewer = tile_viewer::TileViewer::new(options.tile_palette, gameboy.ppu().is_cgb());
+        let (width, height) = viewer.size();
+        screenshot::save_png(path, &viewer.render(gameboy.mmu()), width, height, 1)?;
+    }
+    if let Some(path) = &options.dump_tilemaps {
+        let (width, height) = tilemap_viewer::TilemapViewer.size();
+        screenshot::save_png(path, &tilemap_viewer::TilemapViewer.render(gameboy.mmu()), width, height, 1)?;
+    }
+    if let Some(path) = &options.dump_oam {
+        std::fs::write(path, oam_viewer::listing(gameboy.ppu())).map_err(|e| format!("{}: {}", path, e))?;
+    }
+    gameboy.finish_video()?;
+    gameboy.save_battery()
+}
+
+fn info(rom: &str) -> Result<(), String> {
+    let data = std::fs::read(rom).map_err(|e| format!("{}: {}", rom, e))?;
+    println!("{}", header::Header::parse(&data)?);
+    println!("crc32            {:08x}", crc32fast::hash(&data));
+    Ok(())
+}
+
+// start is an offset into the rom file, addresses are shown as bank:address
+fn disasm(rom: &str, start: usize, count: usize) -> Result<(), String> {
+    let data = std::fs::read(rom).map_err(|e| format!("{}: {}", rom, e))?;
+    if start >= data.len() {
+        return Err(format!("{:#x} is past the end of the rom", start));
+    }
+    // the switchable bank the start offset is in, bank 1 when starting in bank 0
+    let bank = (start / 0x4000).max(1);
+    let read = |address: u16| {
+        let offset = match address {
+            0x0000..=0x3fff => address as usize,
+            _ => bank * 0x4000 + address as usize - 0x4000,
+        };
+        *data.get(offset).unwrap_or(&0xff)
+    };
+    let mut address = if start < 0x4000 { start } else { 0x4000 + start % 0x4000 } as u16;
+    for _ in 0..count {
+        let (text, length) = disasm::disassemble(read, address);
+        let bytes: Vec<String> = (0..length).map(|i| format!("{:02x}", read(address.wrapping_add(i)))).collect();
+        let shown_bank = if address < 0x4000 { 0 } else { bank };
+        println!("{:02x}:{:04x}  {:<9} {}", shown_bank, address, bytes.join(" "), text);
+        address += length;
+        if address >= 0x8000 {
+            break;
+        }
+    }
+    Ok(())
+}
+
+// blargg's tests print passed or failed over serial, mooneye's send
+// the fibonacci numbers 3 5 8 13 21 34 when they pass and 0x42 when they fail
+fn test(rom: &str, frames: u32) -> Result<(), String> {
+    let mut gameboy = Gameboy::new(rom, Box::new(headless_display::HeadlessDisplay))?;
+    let output = Rc::new(RefCell::new(vec![]));
+    gameboy.connect_link(Box::new(serial::Capture(output.clone())));
+    let mut passed = None;
+    for _ in 0..frames {
+        gameboy.run_frame();
+        let output = output.borrow();
+        let text = String::from_utf8_lossy(&output).to_lowercase();
+        if text.contains("passed") || output.ends_with(&[3, 5, 8, 13, 21, 34]) {
+            passed = Some(true);
+        } else if text.contains("failed") || output.ends_with(&[0x42; 6]) {
+            passed = Some(false);
+        }
+        if passed.is_some() {
+            break;
+        }
+    }
+    // the details come after the result
+    gameboy.run_frames(TEST_SETTLE_FRAMES);
+    print!("{}", String::from_utf8_lossy(&output.borrow()));
+    match passed {
+        Some(true) => {
+            println!("\npassed");
+            Ok(())
+        }
+        Some(false) => Err("test failed".to_string()),
+        None => Err(format!("no result after {} frames", frames)),
+    }
+}
@@ -1,4 +1,5 @@
 use crate::mbc::MBC;
+use crate::state::{StateReader, StateWriter};
 
 pub struct MBC0 {
     rom: Vec<u8>,
@@ -20,6 +21,22 @@ impl MBC for MBC0 {
     fn write_ram(&mut self, address: u16, value: u8) {
         () // not used
     }
+
+    fn save_state(&self, _state: &mut StateWriter) {
+        () // no state
+    }
+
+    fn load_state(&mut self, _state: &mut StateReader) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn battery_ram(&self) -> Option<&[u8]> {
+        None
+    }
+
+    fn load_battery_ram(&mut self, _ram: &[u8]) -> Result<(), String> {
+        Ok(())
+    }
 }
 
 impl MBC0 {
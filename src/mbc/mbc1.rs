@@ -1,4 +1,5 @@
 use crate::mbc::MBC;
+use crate::state::{StateReader, StateWriter};
 
 pub struct MBC1 {
     ram_enabled: bool,
@@ -7,6 +8,7 @@ pub struct MBC1 {
     ram: Vec<u8>,
     rom_bank: usize,
     ram_bank: usize,
+    battery: bool,
 }
 
 impl MBC for MBC1 {
@@ -42,43 +44,130 @@ impl MBC for MBC1 {
     }
 
     fn read_ram(&self, address: u16) -> u8 {
-        // if ram enabled, if ram mode and uses ram banking use ram bank number else use 00 bank
-        if self.ram_enabled {
-            let ram_bank = if self.ram_mode { self.ram_bank } else { 0 };
-            self.ram[ram_bank * 0x2000 + (address as usize - 0xa000)]
-        } else {
-            0
+        // nothing drives the bus without enabled ram
+        match self.ram_index(address) {
+            Some(index) => self.ram[index],
+            None => 0xff,
         }
     }
 
     fn write_ram(&mut self, address: u16, value: u8) {
-        if self.ram_enabled {
-            let ram_bank = if self.ram_mode { self.ram_bank } else { 0 };
-            self.ram[ram_bank as usize * 0x2000 + (address as usize - 0xa000)] = value;
+        if let Some(index) = self.ram_index(address) {
+            self.ram[index] = value;
+        }
+    }
+
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_bool(self.ram_enabled);
+        state.write_bool(self.ram_mode);
+        state.write_u32(self.rom_bank as u32);
+        state.write_u32(self.ram_bank as u32);
+        state.write_vec(&self.ram);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), String> {
+        self.ram_enabled = state.read_bool()?;
+        self.ram_mode = state.read_bool()?;
+        self.rom_bank = state.read_u32()? as usize;
+        self.ram_bank = state.read_u32()? as usize;
+        let ram = state.read_vec()?;
+        if ram.len() != self.ram.len() {
+            return Err("save state ram size doesn't match the cartridge".to_string());
+        }
+        if self.rom_bank >= self.rom.len() / 0x4000 || (self.ram_bank > 0 && self.ram_bank * 0x2000 >= ram.len()) {
+            return Err("save state banks are past the end of the cartridge".to_string());
+        }
+        self.ram = ram;
+        Ok(())
+    }
+
+    fn battery_ram(&self) -> Option<&[u8]> {
+        if self.battery && !self.ram.is_empty() {
+            Some(&self.ram)
+        } else {
+            None
+        }
+    }
+
+    fn load_battery_ram(&mut self, ram: &[u8]) -> Result<(), String> {
+        if ram.len() != self.ram.len() {
+            return Err("save file size doesn't match the cartridge ram".to_string());
         }
+        self.ram.copy_from_slice(ram);
+        Ok(())
     }
 }
 
 impl MBC1 {
-    pub fn new(rom: Vec<u8>) -> Self {
-        let ram_size = ram_size(rom[149]);
-        Self {
+    pub fn new(rom: Vec<u8>) -> Result<Self, String> {
+        let ram_size = ram_size(rom[0x149])?;
+        let battery = rom[0x147] == 0x03;
+        Ok(Self {
             ram_enabled: false,
             ram_mode: false,
             rom,
             ram: vec![0; ram_size as usize],
             rom_bank: 1,
             ram_bank: 0,
+            battery,
+        })
+    }
+
+    // if ram mode and uses ram banking use ram bank number else use 00 bank.
+    // banks and addresses past the end of smaller rams wrap around
+    fn ram_index(&self, address: u16) -> Option<usize> {
+        if !self.ram_enabled || self.ram.is_empty() {
+            return None;
         }
+        let ram_bank = if self.ram_mode { self.ram_bank } else { 0 };
+        Some((ram_bank * 0x2000 + (address as usize - 0xa000)) % self.ram.len())
     }
 }
 
-fn ram_size(value: u8) -> u32 {
+fn ram_size(value: u8) -> Result<u32, String> {
     match value {
-        0 => 0,
-        1 => 0x800,
-        2 => 0x2000,
-        3 => 0x8000,
-        _ => panic!("unsupported ram size"),
+        0 => Ok(0),
+        1 => Ok(0x800),
+        2 => Ok(0x2000),
+        3 => Ok(0x8000),
+        n => Err(format!("unsupported cartridge ram size {:#04x}", n)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cartridge(ram_size: u8) -> Result<MBC1, String> {
+        let mut rom = vec![0; 0x8000];
+        rom[0x147] = 0x03;
+        rom[0x149] = ram_size;
+        MBC1::new(rom)
+    }
+
+    #[test]
+    fn carts_without_ram_read_0xff() {
+        let mut mbc = cartridge(0).unwrap();
+        mbc.write_rom(0x0000, 0x0a);
+        mbc.write_ram(0xa000, 0x12);
+        assert_eq!(mbc.read_ram(0xa000), 0xff);
+        assert_eq!(mbc.battery_ram(), None);
+    }
+
+    #[test]
+    fn small_rams_wrap_around() {
+        let mut mbc = cartridge(1).unwrap();
+        mbc.write_rom(0x0000, 0x0a);
+        mbc.write_ram(0xa800, 0x12);
+        assert_eq!(mbc.read_ram(0xa000), 0x12);
+        // a ram bank past the end of the ram
+        mbc.write_rom(0x6000, 0x01);
+        mbc.write_rom(0x4000, 0x03);
+        assert_eq!(mbc.read_ram(0xa000), 0x12);
+    }
+
+    #[test]
+    fn unknown_ram_sizes_are_an_error() {
+        assert!(cartridge(0x09).is_err());
     }
 }
@@ -1,22 +1,28 @@
 mod mbc0; // fix
 mod mbc1;
-use std::fs;
+use crate::state::{StateReader, StateWriter};
 
 pub trait MBC {
     fn read_rom(&self, address: u16) -> u8;
     fn write_rom(&mut self, address: u16, value: u8);
     fn read_ram(&self, address: u16) -> u8;
     fn write_ram(&mut self, address: u16, value: u8);
+    fn save_state(&self, state: &mut StateWriter);
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), String>;
+    // ram kept alive by a battery, saved between sessions
+    fn battery_ram(&self) -> Option<&[u8]>;
+    fn load_battery_ram(&mut self, ram: &[u8]) -> Result<(), String>;
 }
 
 
-pub fn create_mbc(cart_path: &str) -> Box<dyn MBC> {
-    let cart = fs::read(cart_path).expect("File not found");
+pub fn create_mbc(cart: Vec<u8>) -> Result<Box<dyn MBC>, String> {
+    if cart.len() < 0x8000 {
+        return Err("rom is too small".to_string());
+    }
     let mbc_type = cart[0x147];
-    println!("mbc type {}", mbc_type);
     match mbc_type {
-        0 => Box::new(mbc0::MBC0::new(cart)),
-        1 => Box::new(mbc1::MBC1::new(cart)),
-        _ => panic!("Unsupported rom type")
+        0 => Ok(Box::new(mbc0::MBC0::new(cart))),
+        1..=3 => Ok(Box::new(mbc1::MBC1::new(cart)?)),
+        _ => Err(format!("unsupported cartridge type {:#04x}", mbc_type)),
     }
 }
@@ -0,0 +1,224 @@
+use crate::debug_window::DebugView;
+use crate::font::{self, CHAR_HEIGHT, CHAR_WIDTH};
+use crate::mmu::MMU;
+use crate::watch::WatchList;
+use minifb::Key;
+
+// 16 bytes to a row as the cpu would see them, peeked so nothing reacts to the reads.
+// typing two hex digits writes a byte at the cursor, enter goes to the little endian
+// word at the cursor and backspace comes back. g and four digits goes to an address.
+// a watch list is shown to the right and its bytes are highlighted
+const ROWS: usize = 32;
+const BYTES_PER_ROW: usize = 16;
+const MARGIN: usize = 2;
+const HEX_WIDTH: usize = MARGIN + (6 + BYTES_PER_ROW * 3) * CHAR_WIDTH;
+const WATCH_COLUMNS: usize = 30;
+const HEIGHT: usize = MARGIN * 2 + (ROWS + 1) * CHAR_HEIGHT; // the rows and a status line
+const BACKGROUND: u32 = 0x202020;
+const TEXT: u32 = 0xc0c0c0;
+const ADDRESS: u32 = 0x808080;
+const WATCHED: u32 = 0x40c0ff;
+const CURSOR: u32 = 0x806000;
+
+pub struct MemoryViewer {
+    top: u16, // address of the first row
+    cursor: u16,
+    high_nibble: Option<u8>, // first digit of a byte being typed
+    goto: Option<String>,    // digits typed after g
+    history: Vec<u16>,       // cursors before following pointers
+    watch: Option<WatchList>,
+}
+
+impl MemoryViewer {
+    pub fn new(address: u16, watch: Option<WatchList>) -> Self {
+        let mut viewer = Self {
+            top: 0,
+            cursor: 0,
+            high_nibble: None,
+            goto: None,
+            history: vec![],
+            watch,
+        };
+        viewer.move_to(address);
+        viewer
+    }
+
+    // scrolls the cursor's row onto the bottom when it moves a page down or less,
+    // otherwise onto the top
+    fn move_to(&mut self, address: u16) {
+        self.cursor = address;
+        self.high_nibble = None;
+        let row = address & !(BYTES_PER_ROW as u16 - 1);
+        let shown = (ROWS * BYTES_PER_ROW) as u16;
+        let distance = row.wrapping_sub(self.top);
+        if distance >= shown * 2 {
+            self.top = row;
+        } else if distance >= shown {
+            self.top = row.wrapping_sub(shown - BYTES_PER_ROW as u16);
+        }
+    }
+
+    fn type_digit(&mut self, mmu: &mut MMU, digit: u8) {
+        if let Some(goto) = &mut self.goto {
+            goto.push_str(&format!("{:x}", digit));
+            if goto.len() == 4 {
+                let address = u16::from_str_radix(goto, 16).unwrap_or(0);
+                self.goto = None;
+                self.history.push(self.cursor);
+                self.move_to(address);
+            }
+            return;
+        }
+        match self.high_nibble.take() {
+            None => self.high_nibble = Some(digit),
+            Some(high) => {
+                if let Err(e) = mmu.poke(self.cursor, high << 4 | digit) {
+                    println!("{}", e);
+                }
+                self.move_to(self.cursor.wrapping_add(1));
+            }
+        }
+    }
+
+    fn is_watched(&self, address: u16) -> bool {
+        self.watch.as_ref().and_then(|watch| watch.label(address)).is_some()
+    }
+
+    // the address of the byte under the mouse
+    fn address_at(&self, x: usize, y: usize) -> Option<u16> {
+        let (column, row) = (x.checked_sub(MARGIN)? / CHAR_WIDTH, y.checked_sub(MARGIN)? / CHAR_HEIGHT);
+        let byte = column.checked_sub(6)? / 3;
+        if row >= ROWS || byte >= BYTES_PER_ROW {
+            return None;
+        }
+        Some(self.top.wrapping_add((row * BYTES_PER_ROW + byte) as u16))
+    }
+}
+
+impl DebugView for MemoryViewer {
+    fn title(&self) -> String {
+        "Memory, g goes to an address, enter follows a pointer".to_string()
+    }
+
+    fn size(&self) -> (usize, usize) {
+        match self.watch {
+            Some(_) => (HEX_WIDTH + MARGIN + WATCH_COLUMNS * CHAR_WIDTH, HEIGHT),
+            None => (HEX_WIDTH, HEIGHT),
+        }
+    }
+
+    fn render(&self, mmu: &MMU) -> Vec<u32> {
+        let (width, height) = self.size();
+        let mut buffer = vec![BACKGROUND; width * height];
+        for row in 0..ROWS {
+            let y = MARGIN + row * CHAR_HEIGHT;
+            let address = self.top.wrapping_add((row * BYTES_PER_ROW) as u16);
+            font::draw_text(&mut buffer, width, MARGIN, y, &format!("{:04x}", address), ADDRESS);
+            for byte in 0..BYTES_PER_ROW {
+                let address = address.wrapping_add(byte as u16);
+                let x = MARGIN + (6 + byte * 3) * CHAR_WIDTH;
+                let text = match self.high_nibble {
+                    Some(high) if address == self.cursor => format!("{:x}_", high),
+                    _ => format!("{:02x}", mmu.peek(address)),
+                };
+                if address == self.cursor {
+                    for cursor_y in y..y + CHAR_HEIGHT {
+                        for cursor_x in x - 1..x + 2 * CHAR_WIDTH {
+                            buffer[cursor_y * width + cursor_x] = CURSOR;
+                        }
+                    }
+                }
+                let color = if self.is_watched(address) { WATCHED } else { TEXT };
+                font::draw_text(&mut buffer, width, x, y, &text, color);
+            }
+        }
+
+        let word = mmu.peek_word(self.cursor);
+        let status = match &self.goto {
+            Some(goto) => format!("goto {}_", goto),
+            None => format!("{:04x}: {:02x}  word {:04x}", self.cursor, mmu.peek(self.cursor), word),
+        };
+        font::draw_text(&mut buffer, width, MARGIN, MARGIN + ROWS * CHAR_HEIGHT, &status, TEXT);
+
+        if let Some(watch) = &self.watch {
+            let x = HEX_WIDTH + MARGIN;
+            for (row, watch) in watch.watches.iter().take(ROWS + 1).enumerate() {
+                let label: String = watch.label.chars().take(10).collect();
+                let text = format!("{:10} {:04x} {}", label, watch.address, watch.format(watch.read(mmu)));
+                font::draw_text(&mut buffer, width, x, MARGIN + row * CHAR_HEIGHT, &text, WATCHED);
+            }
+        }
+        buffer
+    }
+
+    fn key_pressed(&mut self, mmu: &mut MMU, key: Key) {
+        let page = (ROWS * BYTES_PER_ROW) as u16;
+        match key {
+            Key::Up => self.move_to(self.cursor.wrapping_sub(BYTES_PER_ROW as u16)),
+            Key::Down => self.move_to(self.cursor.wrapping_add(BYTES_PER_ROW as u16)),
+            Key::Left => self.move_to(self.cursor.wrapping_sub(1)),
+            Key::Right => self.move_to(self.cursor.wrapping_add(1)),
+            Key::PageUp => self.move_to(self.cursor.wrapping_sub(page)),
+            Key::PageDown => self.move_to(self.cursor.wrapping_add(page)),
+            Key::G => {
+                self.high_nibble = None;
+                self.goto = Some(String::new());
+            }
+            Key::Enter => {
+                let word = mmu.peek_word(self.cursor);
+                self.history.push(self.cursor);
+                self.move_to(word);
+            }
+            Key::Backspace => match &mut self.goto {
+                Some(goto) => {
+                    goto.pop();
+                }
+                None => {
+                    if let Some(address) = self.history.pop() {
+                        self.move_to(address);
+                    }
+                }
+            },
+            Key::Escape => {
+                self.high_nibble = None;
+                self.goto = None;
+            }
+            key => {
+                if let Some(digit) = hex_digit(key) {
+                    self.type_digit(mmu, digit);
+                }
+            }
+        }
+    }
+
+    fn hover(&self, mmu: &MMU, x: usize, y: usize) -> Option<String> {
+        let address = self.address_at(x, y)?;
+        let mut text = format!("{:04x} = {:02x}", address, mmu.peek(address));
+        if let Some(label) = self.watch.as_ref().and_then(|watch| watch.label(address)) {
+            text += &format!(" {}", label);
+        }
+        Some(text)
+    }
+}
+
+fn hex_digit(key: Key) -> Option<u8> {
+    let digits = [
+        (Key::Key0, Key::NumPad0),
+        (Key::Key1, Key::NumPad1),
+        (Key::Key2, Key::NumPad2),
+        (Key::Key3, Key::NumPad3),
+        (Key::Key4, Key::NumPad4),
+        (Key::Key5, Key::NumPad5),
+        (Key::Key6, Key::NumPad6),
+        (Key::Key7, Key::NumPad7),
+        (Key::Key8, Key::NumPad8),
+        (Key::Key9, Key::NumPad9),
+    ];
+    if let Some(digit) = digits.iter().position(|(key0, numpad)| key == *key0 || key == *numpad) {
+        return Some(digit as u8);
+    }
+    [Key::A, Key::B, Key::C, Key::D, Key::E, Key::F]
+        .iter()
+        .position(|letter| key == *letter)
+        .map(|digit| digit as u8 + 10)
+}
@@ -1,75 +1,106 @@
 extern crate minifb;
-use crate::joypad::Button;
+use crate::bindings::{Hotkey, KeyBindings};
+use crate::input::{self, InputEvent, InputSource};
+use crate::joypad;
+use std::cell::RefCell;
+use std::rc::Rc;
 
 pub trait Display {
     fn update_with_buffer(&mut self, buffer: &[u32]);
-    fn get_buttons_down(&self) -> Vec<Button>;
-    fn get_buttons_up(&self) -> Vec<Button>;
+    fn update(&mut self); // keeps the window responsive without drawing
+    fn is_open(&self) -> bool;
 }
 
 pub struct MinifbDisplay {
-    pub window: minifb::Window,
+    window: Rc<RefCell<minifb::Window>>,
     width: usize,
     height: usize,
 }
 
 impl MinifbDisplay {
-    pub fn new(width: usize, height: usize) -> Box<Self> {
-        let window = match 
-            minifb::Window::new("Rust DMG", width, height, minifb::WindowOptions::default()) {
-                Ok(window) => window,
-                Err(e) => panic!("{}", e)
-            };
-        Box::new(Self {
-            window,
+    // scale is 1, 2, 4 or 8
+    pub fn new(width: usize, height: usize, scale: usize) -> Result<Box<Self>, String> {
+        let scale = match scale {
+            1 => minifb::Scale::X1,
+            2 => minifb::Scale::X2,
+            4 => minifb::Scale::X4,
+            8 => minifb::Scale::X8,
+            n => return Err(format!("unsupported scale {}", n)),
+        };
+        let options = minifb::WindowOptions {
+            scale,
+            ..minifb::WindowOptions::default()
+        };
+        let window = minifb::Window::new("Rust DMG", width, height, options).map_err(|e| e.to_string())?;
+        Ok(Box::new(Self {
+            window: Rc::new(RefCell::new(window)),
             width,
             height,
-        })
+        }))
+    }
+
+    // the keys pressed while the window has focus
+    pub fn keyboard(&self, key_bindings: KeyBindings) -> MinifbKeyboard {
+        MinifbKeyboard {
+            window: self.window.clone(),
+            key_bindings,
+            held: 0xff,
+        }
     }
 }
 
 impl Display for MinifbDisplay {
     fn update_with_buffer(&mut self, buffer: &[u32]) {
        match self.window
+            .borrow_mut()
             .update_with_buffer(buffer, self.width, self.height) {
                 Ok(_) => (),
                 Err(e) => panic!("{}", e)
             }
     }
 
-    fn get_buttons_down(&self) -> Vec<Button> {
-        let mut buttons: Vec<Button> = vec![];
-            for key in self.window.get_keys_pressed(minifb::KeyRepeat::No).unwrap() {
-                match key {
-                    minifb::Key::W => {buttons.push(Button::Up);},
-                    minifb::Key::A => {buttons.push(Button::Left);},
-                    minifb::Key::S => {buttons.push(Button::Down);},
-                    minifb::Key::D => {buttons.push(Button::Right);},
-                    minifb::Key::V => {buttons.push(Button::Start);},
-                    minifb::Key::B => {buttons.push(Button::Select);},
-                    minifb::Key::J => {buttons.push(Button::A);},
-                    minifb::Key::K => {buttons.push(Button::B);},
-                    _=> ()
-                }
-            }
-       buttons
+    fn update(&mut self) {
+        self.window.borrow_mut().update();
     }
 
-    fn get_buttons_up(&self) -> Vec<Button> {
-        let mut buttons: Vec<Button> = vec![];
-            for key in self.window.get_keys_released().unwrap() {
-                match key {
-                    minifb::Key::W => {buttons.push(Button::Up);},
-                    minifb::Key::A => {buttons.push(Button::Left);},
-                    minifb::Key::S => {buttons.push(Button::Down);},
-                    minifb::Key::D => {buttons.push(Button::Right);},
-                    minifb::Key::V => {buttons.push(Button::Start);},
-                    minifb::Key::B => {buttons.push(Button::Select);},
-                    minifb::Key::J => {buttons.push(Button::A);},
-                    minifb::Key::K => {buttons.push(Button::B);},
-                    _=> ()
-                }
-            }
-       buttons
+    fn is_open(&self) -> bool {
+        self.window.borrow().is_open()
+    }
+}
+
+// minifb only takes keys in as the window is updated, so they all land at the start of a frame
+pub struct MinifbKeyboard {
+    window: Rc<RefCell<minifb::Window>>,
+    key_bindings: KeyBindings,
+    held: u8, // buttons held at the last poll, 0 is pressed
+}
+
+impl InputSource for MinifbKeyboard {
+    fn poll(&mut self) -> Vec<InputEvent> {
+        // pressed and released keys only cover the last window update and the window
+        // is also updated while no frame runs, so the keys down are compared instead
+        let keys = self.window.borrow().get_keys().unwrap_or_default();
+        let held = keys
+            .into_iter()
+            .filter_map(|key| self.key_bindings.button(key))
+            .fold(0xff, |held, button| held & !joypad::button_mask(button));
+        let events = input::mask_events(self.held, held);
+        self.held = held;
+        events
+    }
+
+    fn hotkeys_pressed(&mut self) -> Vec<Hotkey> {
+        self.window
+            .borrow()
+            .get_keys_pressed(minifb::KeyRepeat::No)
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|key| self.key_bindings.hotkey(key))
+            .collect()
+    }
+
+    fn hotkey_held(&self, hotkey: Hotkey) -> bool {
+        let window = self.window.borrow();
+        self.key_bindings.hotkey_keys(hotkey).any(|key| window.is_key_down(key))
     }
 }
@@ -1,13 +1,23 @@
 use crate::ppu::PPU;
+use crate::dma::Dma;
+use crate::hdma::Hdma;
 use crate::joypad::Joypad;
 use crate::mbc;
 use crate::serial::Serial;
+use crate::sgb::Sgb;
+use crate::state::{StateReader, StateWriter};
 use crate::timer::Timer;
 
-const WRAM_SIZE: usize = 0x2000;
+const WRAM_SIZE: usize = 0x8000; // 8 banks of 4kb on cgb, dmg only uses 2
+const WRAM_BANK_SIZE: usize = 0x1000;
 const HRAM_SIZE: usize = 0x7f;
 pub struct MMU {
+    pub rom_hash: u32,
+    pub cgb: bool,
+    pub double_speed: bool,
+    speed_switch_armed: bool,
     wram: [u8; WRAM_SIZE],
+    wram_bank: usize,
     hram: [u8; HRAM_SIZE],
     pub interrupt_enable: u8,
     pub interrupt_flag: u8,
@@ -15,43 +25,118 @@ pub struct MMU {
     pub ppu: PPU,
     timer: Timer,
     pub joypad: Joypad,
-    serial: Serial,
+    pub serial: Serial,
+    pub sgb: Option<Sgb>,
+    dma: Dma,
+    hdma: Hdma,
+    hdma_stall: u32, // clocks the cpu is halted for by hdma
+    boot_rom: Vec<u8>,
+    boot_rom_mapped: bool, // until the boot rom writes 0xff50
 }
 
 impl MMU {
-    pub fn new(cart_path: &str) -> Self {
+    pub fn new(cart: Vec<u8>) -> Result<Self, String> {
+        let rom_hash = crc32fast::hash(&cart);
+        let mbc = mbc::create_mbc(cart)?;
+        let cgb = mbc.read_rom(0x143) & 0x80 == 0x80; // cgb flag in the cartridge header
+        // sgb flag, only honoured with the new licensee code
+        let sgb = !cgb && mbc.read_rom(0x146) == 0x03 && mbc.read_rom(0x14b) == 0x33;
         let mut mmu = Self {
+            rom_hash,
+            cgb,
+            double_speed: false,
+            speed_switch_armed: false,
             wram: [0; WRAM_SIZE],
+            wram_bank: 1,
             hram: [0; HRAM_SIZE],
-            mbc: mbc::create_mbc(cart_path),
+            mbc,
             interrupt_enable: 0,
             interrupt_flag: 0,
-            ppu: PPU::new(),
+            ppu: PPU::new(cgb),
             timer: Timer::new(),
             joypad: Joypad::new(),
-            serial: Serial::new(),
+            serial: Serial::new(cgb),
+            sgb: if sgb { Some(Sgb::new()) } else { None },
+            dma: Dma::new(),
+            hdma: Hdma::new(),
+            hdma_stall: 0,
+            boot_rom: vec![],
+            boot_rom_mapped: false,
         };
         mmu.initialize_memory();
-        mmu  
+        Ok(mmu)
+    }
+
+    // the dmg boot rom covers 0x0000-0x00ff, the cgb one also 0x0200-0x08ff
+    pub fn map_boot_rom(&mut self, boot_rom: Vec<u8>) -> Result<(), String> {
+        match (boot_rom.len(), self.cgb) {
+            (0x100, false) | (0x900, true) => (),
+            (0x100, true) => return Err("cgb games need a cgb boot rom".to_string()),
+            (0x900, false) => return Err("dmg games need a dmg boot rom".to_string()),
+            _ => return Err("boot rom should be 256 or 2304 bytes".to_string()),
+        }
+        self.boot_rom = boot_rom;
+        self.boot_rom_mapped = true;
+        self.write_byte(0xff40, 0); // the lcd starts off
+        Ok(())
+    }
+
+    pub fn battery_ram(&self) -> Option<&[u8]> {
+        self.mbc.battery_ram()
+    }
+
+    pub fn load_battery_ram(&mut self, ram: &[u8]) -> Result<(), String> {
+        self.mbc.load_battery_ram(ram)
     }
 
     pub fn read_byte(&self, address: u16) -> u8 {
+        // while oam dma runs the cpu sees the byte being copied instead of anything on
+        // the bus the transfer is reading from. vram has a bus of its own, the rest of
+        // the cartridge and work ram share the other one. this is how the dmg behaves,
+        // the cgb is assumed to be the same
+        if self.dma.is_active() {
+            match address {
+                0xfe00..=0xfe9f => return 0xff,
+                0x0000..=0xfdff | 0xfea0..=0xfeff if self.on_dma_bus(address) => return self.dma.value,
+                _ => (),
+            }
+        }
+        self.read_bus(address)
+    }
+
+    fn read_bus(&self, address: u16) -> u8 {
         match address {
+            0x0000..=0x00ff if self.boot_rom_mapped => self.boot_rom[address as usize],
+            0x0200..=0x08ff if self.boot_rom_mapped && self.cgb => self.boot_rom[address as usize],
             0x00..=0x7fff => self.mbc.read_rom(address),
             0x8000..=0x9fff => self.ppu.read_byte(address),
             0xa000..=0xbfff => self.mbc.read_ram(address), // external ram
-            0xc000..=0xdfff => self.wram[(address - 0xc000) as usize], // work ram
-            0xe000..=0xfdff => self.wram[(address - 0xe000) as usize], // echo ram
+            0xc000..=0xfdff => self.wram[self.wram_index(address)], // work ram and echo ram
             0xfe00..=0xfe9f => self.ppu.read_byte(address), // oam ram
             0xfea0..=0xfeff => 0,                     // usable
-            0xff00 => self.joypad.read_byte(),        // joypad
+            0xff00 => match &self.sgb {
+                Some(sgb) => sgb.read_joypad(self.joypad.read_byte()),
+                None => self.joypad.read_byte(),
+            }, // joypad
             0xff01..=0xff02 => self.serial.read_byte(address), // serial
             0xff03 => 0,
             0xff04..=0xff07 => self.timer.read_byte(address),
             0xff08..=0xff0e => 0,
             0xff0f => self.interrupt_flag, // interrupt flag
             0xff10..=0xff3f => 0,          // sound
-            0xff40..=0xff4b => self.ppu.read_byte(address), // lcd registers
+            0xff40..=0xff45 => self.ppu.read_byte(address), // lcd registers
+            0xff46 => self.dma.read_byte(),
+            0xff47..=0xff4b => self.ppu.read_byte(address),
+            0xff4d if self.cgb => {
+                (if self.double_speed { 0x80 } else { 0 })
+                    | (if self.speed_switch_armed { 0x1 } else { 0 })
+                    | 0x7e
+            }
+            0xff4f if self.cgb => self.ppu.read_byte(address), // vram bank
+            0xff51..=0xff55 if self.cgb => self.hdma.read_byte(address),
+            0xff68..=0xff6b if self.cgb => self.ppu.read_byte(address), // color palettes
+            0xff70 if self.cgb => 0xf8 | self.wram_bank as u8,
+            0xff50 => 0xff,
             0xff4c..=0xff7f => 0,
             0xff80..=0xfffe => self.hram[(address - 0xff80) as usize], // high ram
             0xffff => self.interrupt_enable,
@@ -60,15 +145,48 @@ impl MMU {
     }
 
     pub fn write_byte(&mut self, address: u16, value: u8) {
+        // the same goes for writes, oam is busy whichever bus the transfer reads from
+        let blocked = matches!(address, 0xfe00..=0xfe9f) || (address < 0xff00 && self.on_dma_bus(address));
+        if self.dma.is_active() && blocked {
+            return;
+        }
+        self.write_bus(address, value)
+    }
+
+    // what is at an address whatever oam dma is doing, for debuggers. reads never
+    // change anything so looking at registers doesn't disturb the game
+    pub fn peek(&self, address: u16) -> u8 {
+        self.read_bus(address)
+    }
+
+    pub fn peek_word(&self, address: u16) -> u16 {
+        (self.peek(address) as u16) | ((self.peek(address.wrapping_add(1)) as u16) << 8)
+    }
+
+    // writes like the game would, past oam dma. registers act on the write as usual
+    pub fn poke(&mut self, address: u16, value: u8) -> Result<(), String> {
+        if address < 0x8000 {
+            // writes to rom go to the mbc and switch banks
+            return Err(format!("{:04x} is rom", address));
+        }
+        self.write_bus(address, value);
+        Ok(())
+    }
+
+    fn write_bus(&mut self, address: u16, value: u8) {
         match address {
             0x00..=0x7fff => self.mbc.write_rom(address, value), // 32kb rom bank 00
             0x8000..=0x9fff => self.ppu.write_byte(address, value),
             0xa000..=0xbfff => self.mbc.write_ram(address, value), // external ram
-            0xc000..=0xdfff => self.wram[(address - 0xc000) as usize] = value, // work ram
-            0xe000..=0xfdff => self.wram[(address - 0xe000) as usize] = value, // echo ram
+            0xc000..=0xfdff => self.wram[self.wram_index(address)] = value, // work ram and echo ram
             0xfe00..=0xfe9f => self.ppu.write_byte(address, value), // oam ram
             0xfea0..=0xfeff => (),                          // usable
-            0xff00 => self.joypad.write_byte(value),          // joypad
+            0xff00 => {
+                self.joypad.write_byte(value);
+                if let Some(sgb) = &mut self.sgb {
+                    sgb.write_joypad(value);
+                }
+            } // joypad
             0xff01..=0xff02 => self.serial.write_byte(address, value), // serial
             0xff03 => (),                                   // nothing
             0xff04..=0xff07 => self.timer.write_byte(address, value),
@@ -76,8 +194,28 @@ impl MMU {
             0xff0f => self.interrupt_flag = value,               // interrupt flag
             0xff10..=0xff3f => (),                             // sound
             0xff40..=0xff45 =>self.ppu.write_byte(address, value),
-            0xff46 => self.dma_transfer(value),
+            0xff46 => self.dma.write_byte(value),
             0xff47..=0xff4b => self.ppu.write_byte(address, value),
+            0xff4d if self.cgb => self.speed_switch_armed = value & 0x1 == 0x1,
+            0xff4f if self.cgb => self.ppu.write_byte(address, value),
+            0xff51..=0xff55 if self.cgb => {
+                self.hdma.write_byte(address, value);
+                while self.hdma.general_pending() {
+                    self.hdma_copy_block();
+                }
+            }
+            0xff68..=0xff6b if self.cgb => self.ppu.write_byte(address, value),
+            0xff70 if self.cgb => {
+                self.wram_bank = match value & 0x7 {
+                    0 => 1,
+                    n => n as usize,
+                }
+            }
+            0xff50 => {
+                if value != 0 {
+                    self.boot_rom_mapped = false;
+                }
+            }
             0xff4c..=0xff7f => (),                             // nothing
             0xff80..=0xfffe => self.hram[(address - 0xff80) as usize] = value, // high ram
             0xffff => self.interrupt_enable = value,
@@ -94,11 +232,28 @@ impl MMU {
     }
 
     pub fn tick(&mut self, clocks: u32){
-        self.ppu.tick(clocks);
+        self.tick_dma(clocks);
+        // the ppu keeps its pace when the cpu runs at double speed
+        self.ppu.tick(if self.double_speed { clocks / 2 } else { clocks });
+        if self.ppu.hblank_started && self.hdma.hblank_pending() {
+            self.hdma_copy_block();
+        }
+        // the sgb works on whole frames once vblank starts
+        if self.ppu.interrupt & 0x1 == 0x1 {
+            if let Some(sgb) = &mut self.sgb {
+                if sgb.transfer_pending() {
+                    sgb.complete_transfer(&self.ppu.vram_transfer_data());
+                }
+                sgb.colorize(&self.ppu.shade_data, &mut self.ppu.screen_data);
+            }
+        }
         self.interrupt_flag |= self.ppu.interrupt;
         self.timer.tick(clocks);
         self.interrupt_flag |= self.timer.interrupt;
+        self.serial.tick(clocks);
+        self.interrupt_flag |= self.serial.interrupt;
         self.interrupt_flag |= self.joypad.interrupt;
+        self.joypad.interrupt = 0;
     }
     // inital state after checksum
     fn initialize_memory(&mut self) {
@@ -123,12 +278,221 @@ impl MMU {
         self.write_byte(0xff49, 0xff); // OBP1
     }
 
-    fn dma_transfer(&mut self, data: u8) {
-        let address = (data as u16) << 8 ; 
-        for i in 0x0..0xA0
-        {
-          self.ppu.dma_transfer(i, self.read_byte(address+i));
+    pub fn save_state(&self, state: &mut StateWriter) {
+        state.write_bool(self.double_speed);
+        state.write_bool(self.speed_switch_armed);
+        state.write_bytes(&self.wram);
+        state.write_u8(self.wram_bank as u8);
+        state.write_bytes(&self.hram);
+        state.write_u8(self.interrupt_enable);
+        state.write_u8(self.interrupt_flag);
+        state.write_u32(self.hdma_stall);
+        state.write_bool(self.boot_rom_mapped);
+        self.mbc.save_state(state);
+        self.ppu.save_state(state);
+        self.timer.save_state(state);
+        self.joypad.save_state(state);
+        self.serial.save_state(state);
+        if let Some(sgb) = &self.sgb {
+            sgb.save_state(state);
+        }
+        self.dma.save_state(state);
+        self.hdma.save_state(state);
+    }
+
+    pub fn load_state(&mut self, state: &mut StateReader) -> Result<(), String> {
+        self.double_speed = state.read_bool()?;
+        self.speed_switch_armed = state.read_bool()?;
+        state.read_bytes(&mut self.wram)?;
+        self.wram_bank = (state.read_u8()? as usize & 0x7).max(1);
+        state.read_bytes(&mut self.hram)?;
+        self.interrupt_enable = state.read_u8()?;
+        self.interrupt_flag = state.read_u8()?;
+        self.hdma_stall = state.read_u32()?;
+        // the boot rom itself isn't in the state
+        self.boot_rom_mapped = state.read_bool()? && !self.boot_rom.is_empty();
+        self.mbc.load_state(state)?;
+        self.ppu.load_state(state)?;
+        self.timer.load_state(state)?;
+        self.joypad.load_state(state)?;
+        self.serial.load_state(state)?;
+        if let Some(sgb) = &mut self.sgb {
+            sgb.load_state(state)?;
+        }
+        self.dma.load_state(state)?;
+        self.hdma.load_state(state)?;
+        Ok(())
+    }
+
+    // stop switches speed if it was armed through key1
+    pub fn switch_speed(&mut self) {
+        if self.cgb && self.speed_switch_armed {
+            self.double_speed = !self.double_speed;
+            self.speed_switch_armed = false;
         }
     }
 
+    pub fn take_hdma_stall(&mut self) -> u32 {
+        let stall = self.hdma_stall;
+        self.hdma_stall = 0;
+        stall
+    }
+
+    fn hdma_copy_block(&mut self) {
+        let (source, destination) = self.hdma.next_block();
+        for i in 0..0x10 {
+            let value = self.read_bus(source.wrapping_add(i));
+            self.ppu.write_byte(destination + i, value);
+        }
+        // 8 m-cycles per block, which takes twice the clocks in double speed
+        self.hdma_stall += if self.double_speed { 64 } else { 32 };
+    }
+
+    fn wram_index(&self, address: u16) -> usize {
+        let offset = (address as usize - 0xc000) & 0x1fff;
+        if offset < WRAM_BANK_SIZE {
+            offset
+        } else {
+            self.wram_bank * WRAM_BANK_SIZE + offset - WRAM_BANK_SIZE
+        }
+    }
+
+    // the transfer is stepped after the instruction that took these clocks rather
+    // than between its m-cycles, so every access the instruction made saw the bus
+    // as it was when the instruction started
+    fn tick_dma(&mut self, clocks: u32) {
+        for _ in 0..clocks / 4 {
+            if let Some((source, index)) = self.dma.step() {
+                let value = self.dma_read(source);
+                self.dma.value = value;
+                self.ppu.dma_transfer(index, value);
+            }
+        }
+        self.dma.end_instruction();
+    }
+
+    fn on_dma_bus(&self, address: u16) -> bool {
+        let vram = |address: u16| (0x8000..=0x9fff).contains(&address);
+        vram(address) == vram(self.dma.source())
+    }
+
+    fn dma_read(&self, address: u16) -> u8 {
+        match address {
+            // dma sees wram mirrored all the way up to 0xffff
+            0xe000..=0xffff => self.wram[self.wram_index(address - 0x2000)],
+            _ => self.read_bus(address),
+        }
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OAM_SIZE: u16 = 0xa0;
+
+    // work ram filled with a pattern that differs between the two halves
+    fn mmu() -> MMU {
+        let mut mmu = MMU::new(vec![0; 0x8000]).unwrap();
+        for address in 0xc000..=0xdfff {
+            mmu.write_byte(address, (address as u8) ^ ((address >> 8) as u8));
+        }
+        mmu
+    }
+
+    // the write and then each m-cycle as an instruction of its own
+    fn start_dma(mmu: &mut MMU, page: u8) {
+        mmu.write_byte(0xff46, page);
+        mmu.tick(4);
+    }
+
+    fn oam(mmu: &MMU, index: u16) -> u8 {
+        mmu.peek(0xfe00 + index)
+    }
+
+    #[test]
+    fn dma_waits_an_m_cycle_before_copying() {
+        let mut mmu = mmu();
+        start_dma(&mut mmu, 0xc1);
+        assert!(!mmu.dma.is_active());
+        mmu.tick(4);
+        assert!(mmu.dma.is_active());
+        assert_eq!(oam(&mmu, 0), 0);
+        mmu.tick(4);
+        assert_eq!(oam(&mmu, 0), mmu.peek(0xc100));
+        assert_eq!(oam(&mmu, 1), 0);
+
+        for _ in 1..OAM_SIZE {
+            mmu.tick(4);
+        }
+        assert!(!mmu.dma.is_active());
+        for index in 0..OAM_SIZE {
+            assert_eq!(oam(&mmu, index), mmu.peek(0xc100 + index));
+        }
+    }
+
+    #[test]
+    fn a_restart_keeps_the_bus_blocked_and_starts_over() {
+        let mut mmu = mmu();
+        start_dma(&mut mmu, 0xc1);
+        for _ in 0..11 {
+            mmu.tick(4);
+        }
+        // the old transfer carries on through the startup delay of the new one
+        start_dma(&mut mmu, 0xd2);
+        assert_eq!(oam(&mmu, 10), mmu.peek(0xc10a));
+        mmu.tick(4);
+        assert!(mmu.dma.is_active());
+        assert_eq!(oam(&mmu, 11), mmu.peek(0xc10b));
+        mmu.tick(4);
+        assert_eq!(oam(&mmu, 0), mmu.peek(0xd200));
+
+        for _ in 1..OAM_SIZE {
+            mmu.tick(4);
+        }
+        assert!(!mmu.dma.is_active());
+        for index in 0..OAM_SIZE {
+            assert_eq!(oam(&mmu, index), mmu.peek(0xd200 + index));
+        }
+    }
+
+    #[test]
+    fn sources_from_0xe000_read_work_ram() {
+        for page in [0xe0, 0xfe, 0xff] {
+            let mut mmu = mmu();
+            start_dma(&mut mmu, page);
+            for _ in 0..=OAM_SIZE {
+                mmu.tick(4);
+            }
+            let source = ((page as u16) << 8) - 0x2000;
+            for index in 0..OAM_SIZE {
+                assert_eq!(oam(&mmu, index), mmu.peek(source + index));
+            }
+        }
+    }
+
+    #[test]
+    fn the_dma_bus_reads_the_byte_being_copied() {
+        let mut mmu = mmu();
+        mmu.write_byte(0x8000, 0x42);
+        mmu.write_byte(0xff80, 0x24);
+        start_dma(&mut mmu, 0xc1);
+        mmu.tick(4);
+        for _ in 0..6 {
+            mmu.tick(4);
+        }
+        let value = mmu.peek(0xc105);
+        // work ram and the cartridge share the bus the transfer is on
+        assert_eq!(mmu.read_byte(0xd000), value);
+        assert_eq!(mmu.read_byte(0x0150), value);
+        assert_eq!(mmu.read_byte(0xfe00), 0xff);
+        // vram and high ram are on buses of their own
+        assert_eq!(mmu.read_byte(0x8000), 0x42);
+        assert_eq!(mmu.read_byte(0xff80), 0x24);
+
+        // writes to the blocked bus are dropped
+        mmu.write_byte(0xd000, 0x99);
+        assert_ne!(mmu.peek(0xd000), 0x99);
+    }
 }
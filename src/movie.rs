@@ -0,0 +1,129 @@
+use crate::input::{self, FrameInput, InputEvent, InputSource};
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+
+// movie files start with a header, followed by the joypad changes of every frame
+// magic, version, rom crc32, start flag, then the save state if it doesn't start at power on.
+// a frame is a count of changes then each change's clock and button state,
+// version 1 movies had one byte of button state per frame
+const MAGIC: &[u8; 8] = b"DMGMOVIE";
+const VERSION: u8 = 2;
+const FRAME_STATE_VERSION: u8 = 1;
+const POWER_ON: u8 = 0;
+const SAVE_STATE: u8 = 1;
+
+pub struct Movie {
+    pub rom_hash: u32,
+    pub start_state: Option<Vec<u8>>, // none starts at power on
+    pub buttons: u8, // joypad the movie has got to, 0 is pressed
+    version: u8,
+    frames: Vec<u8>,
+    offset: usize, // where the next frame starts in frames
+}
+
+impl Movie {
+    pub fn load(path: &str) -> Result<Self, String> {
+        let data = fs::read(path).map_err(|e| format!("{}: {}", path, e))?;
+        let invalid = || format!("{} is not a movie file", path);
+        if data.len() < 14 || &data[..8] != MAGIC || (data[8] != VERSION && data[8] != FRAME_STATE_VERSION) {
+            return Err(invalid());
+        }
+        let rom_hash = u32::from_le_bytes([data[9], data[10], data[11], data[12]]);
+        let (start_state, frames_start) = match data[13] {
+            POWER_ON => (None, 14),
+            SAVE_STATE => {
+                if data.len() < 18 {
+                    return Err(invalid());
+                }
+                let length = u32::from_le_bytes([data[14], data[15], data[16], data[17]]) as usize;
+                if data.len() < 18 + length {
+                    return Err(invalid());
+                }
+                (Some(data[18..18 + length].to_vec()), 18 + length)
+            }
+            _ => return Err(invalid()),
+        };
+        Ok(Self {
+            rom_hash,
+            start_state,
+            buttons: 0xff,
+            version: data[8],
+            frames: data[frames_start..].to_vec(),
+            offset: 0,
+        })
+    }
+
+    // joypad changes of the frame at an offset and where the next one starts,
+    // none once the movie is over
+    fn frame_at(&self, offset: usize) -> Option<(FrameInput, usize)> {
+        if self.version == FRAME_STATE_VERSION {
+            let buttons = *self.frames.get(offset)?;
+            return Some((vec![(0, buttons)], offset + 1));
+        }
+        let count = *self.frames.get(offset)? as usize;
+        let changes = self.frames.get(offset + 1..offset + 1 + count * 5)?;
+        let input = changes
+            .chunks(5)
+            .map(|change| (u32::from_le_bytes([change[0], change[1], change[2], change[3]]), change[4]))
+            .collect();
+        Some((input, offset + 1 + count * 5))
+    }
+}
+
+// the recorded joypad changes become presses and releases on the clocks they were recorded at
+impl InputSource for Movie {
+    fn poll(&mut self) -> Vec<InputEvent> {
+        let (input, next) = match self.frame_at(self.offset) {
+            Some(frame) => frame,
+            None => return vec![],
+        };
+        self.offset = next;
+        let mut events = vec![];
+        for (clock, buttons) in input {
+            let changes = input::mask_events(self.buttons, buttons);
+            events.extend(changes.into_iter().map(|event| InputEvent { clock, ..event }));
+            self.buttons = buttons;
+        }
+        events
+    }
+
+    fn finished(&self) -> bool {
+        self.frame_at(self.offset).is_none()
+    }
+}
+
+// frames are written as they happen so a recording survives the emulator being closed
+pub struct MovieRecorder {
+    file: BufWriter<File>,
+}
+
+impl MovieRecorder {
+    pub fn create(path: &str, rom_hash: u32, start_state: Option<&[u8]>) -> io::Result<Self> {
+        let mut file = BufWriter::new(File::create(path)?);
+        file.write_all(MAGIC)?;
+        file.write_all(&[VERSION])?;
+        file.write_all(&rom_hash.to_le_bytes())?;
+        match start_state {
+            Some(state) => {
+                file.write_all(&[SAVE_STATE])?;
+                file.write_all(&(state.len() as u32).to_le_bytes())?;
+                file.write_all(state)?;
+            }
+            None => file.write_all(&[POWER_ON])?,
+        }
+        file.flush()?;
+        Ok(Self { file })
+    }
+
+    pub fn record_frame(&mut self, input: &[(u32, u8)]) -> io::Result<()> {
+        if input.len() > 255 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "too many button changes in a frame"));
+        }
+        self.file.write_all(&[input.len() as u8])?;
+        for (clock, buttons) in input {
+            self.file.write_all(&clock.to_le_bytes())?;
+            self.file.write_all(&[*buttons])?;
+        }
+        self.file.flush()
+    }
+}
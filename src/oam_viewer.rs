@@ -0,0 +1,178 @@
+use crate::debug_window::DebugView;
+use crate::mmu::MMU;
+use crate::ppu::PPU;
+use crate::utills::check_bit;
+
+// the 40 oam entries drawn 8 to a row on the left, outlined green if any line drew them.
+// on the right each entry has a column and each line a row, green where the line drew
+// the sprite and red where it was on the line but past the 10 sprite limit
+const SPRITES: usize = 40;
+const COLUMNS: usize = 8;
+const CELL_WIDTH: usize = 10; // an 8x16 sprite with a border around it
+const CELL_HEIGHT: usize = 18;
+const LINES_LEFT: usize = COLUMNS * CELL_WIDTH + 4;
+const LINE_COLUMN: usize = 2; // pixels per entry in the line chart
+const WIDTH: usize = LINES_LEFT + SPRITES * LINE_COLUMN;
+const HEIGHT: usize = 144;
+const BACKGROUND: u32 = 0x404040;
+const BORDER: u32 = 0x808080;
+const SELECTED: u32 = 0x00c000;
+const DROPPED: u32 = 0xc00000;
+
+pub struct OamViewer;
+
+impl DebugView for OamViewer {
+    fn title(&self) -> String {
+        "OAM, l lists the entries".to_string()
+    }
+
+    fn size(&self) -> (usize, usize) {
+        (WIDTH, HEIGHT)
+    }
+
+    fn render(&self, mmu: &MMU) -> Vec<u32> {
+        let ppu = &mmu.ppu;
+        let mut buffer = vec![BACKGROUND; WIDTH * HEIGHT];
+        let height = sprite_height(ppu);
+        let drawn = (0..HEIGHT).fold(0, |drawn, line| drawn | ppu.selected_sprites(line));
+        for sprite in 0..SPRITES {
+            let left = (sprite % COLUMNS) * CELL_WIDTH;
+            let top = (sprite / COLUMNS) * CELL_HEIGHT;
+            let border = if drawn & 1 << sprite != 0 { SELECTED } else { BORDER };
+            for y in 0..CELL_HEIGHT {
+                for x in 0..CELL_WIDTH {
+                    buffer[(top + y) * WIDTH + left + x] = border;
+                }
+            }
+            for (y, row) in sprite_pixels(ppu, sprite).iter().enumerate() {
+                for (x, color) in row.iter().enumerate() {
+                    buffer[(top + 1 + y) * WIDTH + left + 1 + x] = *color;
+                }
+            }
+            // 8x8 sprites leave the bottom of the cell empty
+            for y in height..16 {
+                for x in 0..8 {
+                    buffer[(top + 1 + y) * WIDTH + left + 1 + x] = BACKGROUND;
+                }
+            }
+        }
+
+        for line in 0..HEIGHT {
+            let selected = ppu.selected_sprites(line);
+            for sprite in 0..SPRITES {
+                let color = if selected & 1 << sprite != 0 {
+                    SELECTED
+                } else if on_line(ppu, sprite, line) {
+                    DROPPED
+                } else {
+                    continue;
+                };
+                for x in 0..LINE_COLUMN {
+                    buffer[line * WIDTH + LINES_LEFT + sprite * LINE_COLUMN + x] = color;
+                }
+            }
+        }
+        buffer
+    }
+
+    fn key_pressed(&mut self, mmu: &mut MMU, key: minifb::Key) {
+        if key == minifb::Key::L {
+            print!("{}", listing(&mmu.ppu));
+        }
+    }
+
+    fn hover(&self, mmu: &MMU, x: usize, y: usize) -> Option<String> {
+        let ppu = &mmu.ppu;
+        if x < COLUMNS * CELL_WIDTH {
+            let sprite = (y / CELL_HEIGHT) * COLUMNS + x / CELL_WIDTH;
+            if sprite < SPRITES {
+                return Some(describe(ppu, sprite));
+            }
+        } else if x >= LINES_LEFT && y < HEIGHT {
+            let sprite = (x - LINES_LEFT) / LINE_COLUMN;
+            let state = if ppu.selected_sprites(y) & 1 << sprite != 0 {
+                "drawn"
+            } else if on_line(ppu, sprite, y) {
+                "dropped"
+            } else {
+                "not on the line"
+            };
+            return Some(format!("line {} {} {}", y, describe(ppu, sprite), state));
+        }
+        None
+    }
+}
+
+fn sprite_height(ppu: &PPU) -> usize {
+    if check_bit(ppu.read_byte(0xff40), 2) {
+        16
+    } else {
+        8
+    }
+}
+
+fn on_line(ppu: &PPU, sprite: usize, line: usize) -> bool {
+    let y = ppu.oam_entry(sprite)[0] as usize;
+    y <= line + 16 && line + 16 < y + sprite_height(ppu)
+}
+
+// the sprite as drawn, flips and palette included, colour 0 is transparent
+fn sprite_pixels(ppu: &PPU, sprite: usize) -> Vec<[u32; 8]> {
+    let [_, _, tile, attributes] = ppu.oam_entry(sprite);
+    let height = sprite_height(ppu);
+    let tile = if height == 16 { tile & 0xfe } else { tile } as usize;
+    let bank = if ppu.is_cgb() && check_bit(attributes, 3) { 1 } else { 0 };
+    let colors = ppu.sprite_colors(attributes);
+    (0..height)
+        .map(|y| {
+            let row = if check_bit(attributes, 6) { height - 1 - y } else { y };
+            let mut color_numbers = ppu.tile_row(bank, tile * 16, row);
+            if check_bit(attributes, 5) {
+                color_numbers.reverse();
+            }
+            let mut pixels = [BACKGROUND; 8];
+            for (pixel, color_number) in pixels.iter_mut().zip(color_numbers.iter()) {
+                if *color_number != 0 {
+                    *pixel = colors[*color_number as usize];
+                }
+            }
+            pixels
+        })
+        .collect()
+}
+
+// one line about an entry, positions are as oam holds them
+fn describe(ppu: &PPU, sprite: usize) -> String {
+    let [y, x, tile, attributes] = ppu.oam_entry(sprite);
+    let mut flags = vec![];
+    if check_bit(attributes, 7) {
+        flags.push("behind bg".to_string());
+    }
+    if check_bit(attributes, 6) {
+        flags.push("y flip".to_string());
+    }
+    if check_bit(attributes, 5) {
+        flags.push("x flip".to_string());
+    }
+    if ppu.is_cgb() {
+        flags.push(format!("bank {}", (attributes >> 3) & 1));
+        flags.push(format!("palette {}", attributes & 0x7));
+    } else {
+        flags.push(if check_bit(attributes, 4) { "obp1" } else { "obp0" }.to_string());
+    }
+    format!("sprite {:2} x {:3} y {:3} tile {:#04x} {}", sprite, x, y, tile, flags.join(", "))
+}
+
+// every entry and the lines that drew it
+pub fn listing(ppu: &PPU) -> String {
+    let mut text = String::new();
+    for sprite in 0..SPRITES {
+        let lines: Vec<usize> = (0..HEIGHT).filter(|line| ppu.selected_sprites(*line) & 1 << sprite != 0).collect();
+        let drawn = match (lines.first(), lines.last()) {
+            (Some(first), Some(last)) => format!(", drawn on {} lines from {} to {}", lines.len(), first, last),
+            _ => String::new(),
+        };
+        text += &format!("{}{}\n", describe(ppu, sprite), drawn);
+    }
+    text
+}
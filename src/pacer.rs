@@ -0,0 +1,81 @@
+use crate::gameboy::CLOCKS_PER_FRAME;
+use std::time::{Duration, Instant};
+
+const CLOCK_SPEED: f64 = 4194304.0;
+const MIN_SPEED: f64 = 0.125;
+const MAX_SPEED: f64 = 8.0;
+const MAX_LAG_FRAMES: u32 = 4; // further behind than this and the pacer stops catching up
+
+// keeps frames at the real 59.73 hz against a monotonic clock, the time spent
+// emulating is taken out of the wait so the pace doesn't drift.
+// once there is sound the audio queue should drive this instead
+pub struct FramePacer {
+    speed: Option<f64>, // multiplier of real speed, none runs uncapped
+    fast_forward: bool, // uncapped while held
+    paused: bool,
+    advance: bool, // run one frame while paused
+    next_frame: Instant,
+}
+
+impl FramePacer {
+    pub fn new(speed: Option<f64>) -> Self {
+        Self {
+            speed: speed.map(|speed| speed.clamp(MIN_SPEED, MAX_SPEED)),
+            fast_forward: false,
+            paused: false,
+            advance: false,
+            next_frame: Instant::now(),
+        }
+    }
+
+    pub fn toggle_pause(&mut self) {
+        self.paused = !self.paused;
+    }
+
+    pub fn advance_frame(&mut self) {
+        self.paused = true;
+        self.advance = true;
+    }
+
+    pub fn set_fast_forward(&mut self, fast_forward: bool) {
+        self.fast_forward = fast_forward;
+    }
+
+    // doubles or halves the speed, uncapped stays uncapped
+    pub fn speed_up(&mut self) {
+        self.speed = self.speed.map(|speed| (speed * 2.0).min(MAX_SPEED));
+    }
+
+    pub fn slow_down(&mut self) {
+        self.speed = self.speed.map(|speed| (speed / 2.0).max(MIN_SPEED));
+    }
+
+    // whether the next frame should be emulated
+    pub fn frame_due(&mut self) -> bool {
+        if self.paused {
+            std::mem::replace(&mut self.advance, false)
+        } else {
+            true
+        }
+    }
+
+    // sleeps until the next frame is due, paused frames are paced at normal speed
+    pub fn wait(&mut self) {
+        let speed = match self.speed {
+            _ if self.paused => 1.0,
+            Some(speed) if !self.fast_forward => speed,
+            _ => {
+                self.next_frame = Instant::now();
+                return;
+            }
+        };
+        let frame_time = Duration::from_secs_f64(CLOCKS_PER_FRAME as f64 / (CLOCK_SPEED * speed));
+        self.next_frame += frame_time;
+        let now = Instant::now();
+        if self.next_frame > now {
+            std::thread::sleep(self.next_frame - now);
+        } else if now - self.next_frame > frame_time * MAX_LAG_FRAMES {
+            self.next_frame = now;
+        }
+    }
+}
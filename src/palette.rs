@@ -0,0 +1,112 @@
+use serde::Deserialize;
+use std::fs;
+
+// the four shades a dmg color number can be mapped to, lightest first
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Palette(pub [u32; 4]);
+
+impl Palette {
+    pub const GREY: Palette = Palette([0xffffff, 0xd3d3d3, 0xa9a9a9, 0x000000]);
+    pub const GREEN: Palette = Palette([0x9bbc0f, 0x8bac0f, 0x306230, 0x0f380f]);
+    pub const POCKET: Palette = Palette([0xc4cfa1, 0x8b956d, 0x4d533c, 0x1f1f1f]);
+    pub const LIGHT: Palette = Palette([0x00b581, 0x009a71, 0x00694a, 0x004f3b]);
+
+    pub fn preset(name: &str) -> Option<Palette> {
+        match name.to_lowercase().as_str() {
+            "grey" | "gray" => Some(Palette::GREY),
+            "green" | "dmg" => Some(Palette::GREEN),
+            "pocket" => Some(Palette::POCKET),
+            "light" => Some(Palette::LIGHT),
+            _ => None,
+        }
+    }
+
+    pub fn color(&self, shade: u8) -> u32 {
+        self.0[shade as usize]
+    }
+}
+
+// separate palettes for the background and both sprite palettes
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Palettes {
+    pub bg: Palette,
+    pub obj0: Palette,
+    pub obj1: Palette,
+}
+
+impl Palettes {
+    pub fn new(palette: Palette) -> Self {
+        Self {
+            bg: palette,
+            obj0: palette,
+            obj1: palette,
+        }
+    }
+
+    // a preset name or the path of a palette file
+    pub fn load(name_or_path: &str) -> Result<Self, String> {
+        match Palette::preset(name_or_path) {
+            Some(palette) => Ok(Palettes::new(palette)),
+            None => Palettes::from_file(name_or_path),
+        }
+    }
+
+    // palette files are toml, each palette is a preset name or four colors:
+    // bg = ["#e0f8d0", "#88c070", "#346856", "#081820"]
+    // obj0 = "pocket"
+    // obj0 falls back to the background palette and obj1 to obj0
+    pub fn from_file(path: &str) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+        let file: PaletteFile = toml::from_str(&text).map_err(|e| format!("{}: {}", path, e))?;
+        let bg = file.bg.to_palette()?;
+        let obj0 = match file.obj0 {
+            Some(entry) => entry.to_palette()?,
+            None => bg,
+        };
+        let obj1 = match file.obj1 {
+            Some(entry) => entry.to_palette()?,
+            None => obj0,
+        };
+        Ok(Self { bg, obj0, obj1 })
+    }
+}
+
+#[derive(Deserialize)]
+struct PaletteFile {
+    bg: PaletteEntry,
+    obj0: Option<PaletteEntry>,
+    obj1: Option<PaletteEntry>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+pub enum PaletteEntry {
+    Preset(String),
+    Colors([String; 4]),
+}
+
+impl PaletteEntry {
+    pub fn to_palette(&self) -> Result<Palette, String> {
+        match self {
+            PaletteEntry::Preset(name) => {
+                Palette::preset(name).ok_or_else(|| format!("unknown palette {}", name))
+            }
+            PaletteEntry::Colors(colors) => {
+                let mut palette = [0; 4];
+                for (shade, color) in colors.iter().enumerate() {
+                    palette[shade] = parse_color(color)?;
+                }
+                Ok(Palette(palette))
+            }
+        }
+    }
+}
+
+// colors are written as hex, "#rrggbb" or "rrggbb"
+fn parse_color(color: &str) -> Result<u32, String> {
+    let hex = color.trim_start_matches('#');
+    if hex.len() != 6 {
+        return Err(format!("invalid color {}", color));
+    }
+    u32::from_str_radix(hex, 16).map_err(|_| format!("invalid color {}", color))
+}
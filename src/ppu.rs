@@ -1,6 +1,9 @@
-use crate::utills::{check_bit, get_bit_value};
+use crate::palette::{Palette, Palettes};
+use crate::state::{StateReader, StateWriter};
+use crate::utills::{check_bit, get_bit_value, rgb555_to_rgb888};
 const VRAM_SIZE: usize = 0x2000;
 const OAM_RAM_SIZE: usize = 0xa0;
+const PALETTE_RAM_SIZE: usize = 0x40;
 const SCREEN_WIDTH: usize = 160;
 const SCREEN_HEIGHT: usize = 144;
 #[derive(Clone, Copy, PartialEq)]
@@ -11,20 +14,42 @@ enum Mode {
     LCDTransfer,
 }
 use Mode::{HBlank, LCDTransfer, OAMSearch, VBlank};
-#[derive(PartialEq)]
-enum Color {
-    White = 0xffffff,
-    LightGrey = 0xd3d3d3,
-    DarkGrey = 0xa9a9a9,
-    Black = 0x0,
+// debug switches for each layer, the game still sees lcdc as it wrote it
+#[derive(Clone, Copy, PartialEq)]
+pub struct Layers {
+    pub background: bool,
+    pub window: bool, // the window isn't drawn yet, this is for when it is
+    pub sprites: bool,
+    pub cgb_priority: bool, // off draws cgb sprites above the background whatever the attributes say
 }
 
-use Color::{Black, DarkGrey, LightGrey, White};
+impl Layers {
+    pub fn new() -> Self {
+        Self {
+            background: true,
+            window: true,
+            sprites: true,
+            cgb_priority: true,
+        }
+    }
+}
 
+// the colors debug views draw tiles in, on cgb the register palettes stand
+// for background palette 0 and sprite palettes 0 and 1
+#[derive(Clone, Copy, PartialEq)]
+pub enum DebugPalette {
+    Bgp,
+    Obp0,
+    Obp1,
+    Grey,
+}
 pub struct PPU {
-    vram: [u8; VRAM_SIZE],
+    cgb: bool,
+    vram: [u8; VRAM_SIZE * 2], // bank 1 is only used in cgb mode
+    vram_bank: usize,
     oam_ram: [u8; OAM_RAM_SIZE],
     pub screen_data: [u32; SCREEN_WIDTH * SCREEN_HEIGHT],
+    pub shade_data: [u8; SCREEN_WIDTH * SCREEN_HEIGHT], // dmg shades before they become colors
     scy: u8,
     scx: u8,
     ly: u8,
@@ -34,9 +59,19 @@ pub struct PPU {
     bgp: u8,
     obp0: u8,
     obp1: u8,
+    pub palettes: Palettes, // output colors for dmg shades
+    pub layers: Layers,
+    bcps: u8, // cgb background palette index
+    ocps: u8, // cgb sprite palette index
+    bg_palette_ram: [u8; PALETTE_RAM_SIZE],
+    obj_palette_ram: [u8; PALETTE_RAM_SIZE],
+    bg_color_numbers: [u8; SCREEN_WIDTH], // background colors of the current line
+    bg_attr_priority: [bool; SCREEN_WIDTH], // cgb tile attribute priority of the current line
+    selected_sprites: [u64; SCREEN_HEIGHT], // bit n set if oam entry n was drawn on the line, for debugging
     mode: Mode,
     clocks: u32,
     pub interrupt: u8,
+    pub hblank_started: bool,
     display_enabled: bool,
     window_tilemap: u16,
     window_enabled: bool,
@@ -53,11 +88,14 @@ pub struct PPU {
 }
 
 impl PPU {
-    pub fn new() -> Self {
+    pub fn new(cgb: bool) -> Self {
         Self {
-            vram: [0; VRAM_SIZE],
+            cgb,
+            vram: [0; VRAM_SIZE * 2],
+            vram_bank: 0,
             oam_ram: [0; OAM_RAM_SIZE],
             screen_data: [0; SCREEN_WIDTH * SCREEN_HEIGHT],
+            shade_data: [0; SCREEN_WIDTH * SCREEN_HEIGHT],
             scy: 0,
             scx: 0,
             ly: 0,
@@ -67,9 +105,19 @@ impl PPU {
             bgp: 0,
             obp0: 0,
             obp1: 0,
+            palettes: Palettes::new(Palette::GREY),
+            layers: Layers::new(),
+            bcps: 0,
+            ocps: 0,
+            bg_palette_ram: [0xff; PALETTE_RAM_SIZE],
+            obj_palette_ram: [0xff; PALETTE_RAM_SIZE],
+            bg_color_numbers: [0; SCREEN_WIDTH],
+            bg_attr_priority: [false; SCREEN_WIDTH],
+            selected_sprites: [0; SCREEN_HEIGHT],
             mode: OAMSearch,
             clocks: 0,
             interrupt: 0,
+            hblank_started: false,
             display_enabled: false,
             window_tilemap: 0x1c00,
             window_enabled: false,
@@ -88,7 +136,7 @@ impl PPU {
 
     pub fn read_byte(&self, address: u16) -> u8 {
         match address {
-            0x8000..=0x9fff => self.vram[(address - 0x8000) as usize],
+            0x8000..=0x9fff => self.vram[self.vram_bank * VRAM_SIZE + (address - 0x8000) as usize],
             0xfe00..=0xfe9f => self.oam_ram[(address - 0xfe00) as usize],
             0xff40 => {
                 (if self.display_enabled { 0x80 } else { 0 })
@@ -134,13 +182,18 @@ impl PPU {
             0xff49 => self.obp1,
             0xff4a => self.wy,
             0xff4b => self.wx,
+            0xff4f => 0xfe | self.vram_bank as u8,
+            0xff68 => self.bcps | 0x40,
+            0xff69 => self.bg_palette_ram[(self.bcps & 0x3f) as usize],
+            0xff6a => self.ocps | 0x40,
+            0xff6b => self.obj_palette_ram[(self.ocps & 0x3f) as usize],
             _ => panic!("address {:#x} is not handled by gpu", address),
         }
     }
 
     pub fn write_byte(&mut self, address: u16, value: u8) {
         match address {
-            0x8000..=0x9fff => self.vram[(address - 0x8000) as usize] = value,
+            0x8000..=0x9fff => self.vram[self.vram_bank * VRAM_SIZE + (address - 0x8000) as usize] = value,
             0xfe00..=0xfe9f => self.oam_ram[(address - 0xfe00) as usize] = value,
             0xff40 => {
                 self.display_enabled = value & 0x80 == 0x80;
@@ -168,14 +221,80 @@ impl PPU {
             0xff49 => self.obp1 = value,
             0xff4a => self.wy = value,
             0xff4b => self.wx = value,
+            0xff4f => self.vram_bank = (value & 0x1) as usize,
+            0xff68 => self.bcps = value & 0xbf,
+            0xff69 => {
+                self.bg_palette_ram[(self.bcps & 0x3f) as usize] = value;
+                self.bcps = increment_palette_index(self.bcps);
+            }
+            0xff6a => self.ocps = value & 0xbf,
+            0xff6b => {
+                self.obj_palette_ram[(self.ocps & 0x3f) as usize] = value;
+                self.ocps = increment_palette_index(self.ocps);
+            }
             _ => panic!("address {:#x} is not handled by gpu", address),
         }
     }
 
+    // palettes are a user setting and are left alone
+    pub fn save_state(&self, state: &mut StateWriter) {
+        state.write_bytes(&self.vram);
+        state.write_u8(self.vram_bank as u8);
+        state.write_bytes(&self.oam_ram);
+        for pixel in self.screen_data.iter() {
+            state.write_u32(*pixel);
+        }
+        state.write_bytes(&self.shade_data);
+        for register in [self.scy, self.scx, self.ly, self.lyc, self.wy, self.wx].iter() {
+            state.write_u8(*register);
+        }
+        for register in [self.bgp, self.obp0, self.obp1, self.bcps, self.ocps].iter() {
+            state.write_u8(*register);
+        }
+        state.write_bytes(&self.bg_palette_ram);
+        state.write_bytes(&self.obj_palette_ram);
+        state.write_u8(self.mode as u8);
+        state.write_u32(self.clocks);
+        state.write_u8(self.read_byte(0xff40));
+        state.write_u8(self.read_byte(0xff41));
+    }
+
+    pub fn load_state(&mut self, state: &mut StateReader) -> Result<(), String> {
+        state.read_bytes(&mut self.vram)?;
+        self.vram_bank = state.read_u8()? as usize & 0x1;
+        state.read_bytes(&mut self.oam_ram)?;
+        for pixel in self.screen_data.iter_mut() {
+            *pixel = state.read_u32()?;
+        }
+        state.read_bytes(&mut self.shade_data)?;
+        for register in [&mut self.scy, &mut self.scx, &mut self.ly, &mut self.lyc, &mut self.wy, &mut self.wx].iter_mut() {
+            **register = state.read_u8()?;
+        }
+        for register in [&mut self.bgp, &mut self.obp0, &mut self.obp1, &mut self.bcps, &mut self.ocps].iter_mut() {
+            **register = state.read_u8()?;
+        }
+        state.read_bytes(&mut self.bg_palette_ram)?;
+        state.read_bytes(&mut self.obj_palette_ram)?;
+        self.mode = match state.read_u8()? & 0x3 {
+            0 => HBlank,
+            1 => VBlank,
+            2 => OAMSearch,
+            _ => LCDTransfer,
+        };
+        self.clocks = state.read_u32()?;
+        // lcdc and stat are restored through their registers
+        let lcdc = state.read_u8()?;
+        let stat = state.read_u8()?;
+        self.write_byte(0xff40, lcdc);
+        self.write_byte(0xff41, stat);
+        Ok(())
+    }
+
     pub fn tick(&mut self, clocks: u32) {
         // TODO account for variable number of dot clocks
         // reset interrrupts
         self.interrupt = 0;
+        self.hblank_started = false;
         self.clocks += clocks;
         match self.mode {
             // 80 dots
@@ -231,6 +350,7 @@ impl PPU {
         if match mode {
             HBlank => {
                 self.buffer_scanline();
+                self.hblank_started = true;
                 self.hblank_interrupt_enabled
             }
             VBlank => {
@@ -258,55 +378,77 @@ impl PPU {
         }
 
         let bg_y = self.scy.wrapping_add(self.ly);
-        let bg_tile_row = (bg_y / 8) as u16 * 32;
         for pixel in 0..SCREEN_WIDTH {
-            let x = self.scx.wrapping_add(pixel as u8);
-            let bg_tile_col = (x / 8) as u16;
-            let tile_num =
-                self.vram[(self.background_tilemap + bg_tile_row + bg_tile_col) as usize];
-            let data_address = match self.tilebase {
-                0x0000 => self.tilebase + (tile_num as u16 * 16),
-                0x1000 => (self.tilebase as i32 + (tile_num as i8 as i16 as i32 * 16)) as u16,
-                _ => unreachable!("not a valid tile base"),
+            let current_pixel_address = self.ly as usize * SCREEN_WIDTH + pixel;
+            // on dmg clearing lcdc bit 0 blanks the background
+            if !self.cgb && !self.bg_win_priority {
+                self.bg_color_numbers[pixel] = 0;
+                self.bg_attr_priority[pixel] = false;
+                self.set_dmg_pixel(current_pixel_address, 0, self.palettes.bg);
+                continue;
+            }
+
+            let (color_number, attributes) = if self.layers.background {
+                let x = self.scx.wrapping_add(pixel as u8);
+                self.tilemap_pixel(self.background_tilemap, x, bg_y)
+            } else {
+                // a hidden background draws as color 0
+                (0, 0)
             };
 
-            let line = ((bg_y % 8) * 2) as u16;
-            let line_byte1 = self.vram[(data_address + line) as usize];
-            let line_byte2 = self.vram[(data_address + line + 1) as usize];
-            let color_bit = 7 - (x % 8);
-            let mut color_number = get_bit_value(line_byte2, color_bit) << 1;
-            color_number |= get_bit_value(line_byte1, color_bit);
-            let color = self.map_color_pattel(color_number, self.bgp);
-            self.screen_data[self.ly as usize * SCREEN_WIDTH as usize + pixel] = color as u32;
+            self.bg_color_numbers[pixel] = color_number;
+            self.bg_attr_priority[pixel] = check_bit(attributes, 7);
+            if self.cgb {
+                self.screen_data[current_pixel_address] =
+                    cgb_color(&self.bg_palette_ram, attributes & 0x7, color_number);
+            } else {
+                let shade = self.map_color_pattel(color_number, self.bgp);
+                self.set_dmg_pixel(current_pixel_address, shade, self.palettes.bg);
+            }
         }
     }
 
     fn scanline_sprites(&mut self) {
+        self.selected_sprites[self.ly as usize] = 0;
         if !self.sprites_enabled {
             return;
         };
-        // search through oam for visable sprites
-        let bytes = self.oam_ram.chunks(4);
-        for byte in bytes {
-            let sprite_y = byte[0] as i32 - 16;
-            let sprite_x = byte[1] as i32 - 8;
-            // sprite not visable
-            if sprite_y == -16 || sprite_y > 143 || sprite_x == -8 || sprite_x > 159 {
-                continue;
-            }
-            let sprite_size = self.sprite_size;
-            // does sprite intercept scanline
-            let ly = self.ly as i32;
-            if sprite_y > ly || sprite_y + sprite_size as i32 <= ly {
-                continue;
+        let ly = self.ly as i32;
+        let sprite_size = self.sprite_size as i32;
+        // search through oam for the first 10 sprites on the scanline
+        let mut sprites: Vec<usize> = (0..OAM_RAM_SIZE / 4)
+            .filter(|sprite| {
+                let sprite_y = self.oam_ram[sprite * 4] as i32 - 16;
+                sprite_y <= ly && ly < sprite_y + sprite_size
+            })
+            .take(10)
+            .collect();
+        self.selected_sprites[self.ly as usize] = sprites.iter().fold(0, |selected, sprite| selected | 1 << sprite);
+        // dmg gives the lowest x priority, cgb only goes by oam order
+        if !self.cgb {
+            sprites.sort_by_key(|sprite| self.oam_ram[sprite * 4 + 1]);
+        }
+
+        if !self.layers.sprites {
+            return;
+        }
+        // pixels already taken by a sprite with higher priority
+        let mut drawn = [false; SCREEN_WIDTH];
+        for sprite in sprites {
+            let address = sprite * 4;
+            let sprite_y = self.oam_ram[address] as i32 - 16;
+            let sprite_x = self.oam_ram[address + 1] as i32 - 8;
+            let mut tile_number = self.oam_ram[address + 2] as u16;
+            let attributes = self.oam_ram[address + 3];
+            if sprite_size == 16 {
+                tile_number &= 0xfe;
             }
-            let tile_number = byte[2] as u16;
-            let attributes = byte[3];
             let y_flip = check_bit(attributes, 6);
             let x_flip = check_bit(attributes, 5);
-            let palette = match check_bit(attributes, 4) {
-                true => self.obp1,
-                false => self.obp0,
+            let bank = if self.cgb && check_bit(attributes, 3) {
+                VRAM_SIZE
+            } else {
+                0
             };
 
             let mut line = (ly - sprite_y) as u16;
@@ -314,77 +456,203 @@ impl PPU {
                 line = (self.sprite_size as u16 - 1) - line;
             }
             line *= 2;
-            let data1 = self.vram[((tile_number * 16) + line) as usize];
-            let data2 = self.vram[((tile_number * 16) + line + 1) as usize];
+            let data1 = self.vram[bank + ((tile_number * 16) + line) as usize];
+            let data2 = self.vram[bank + ((tile_number * 16) + line + 1) as usize];
 
             for pixel in 0..8 {
+                let x_pix = sprite_x + pixel as i32;
+                if x_pix < 0 || x_pix >= SCREEN_WIDTH as i32 || drawn[x_pix as usize] {
+                    continue;
+                }
                 let mut tile_pixel = pixel;
                 if !x_flip {
                     tile_pixel = 7 - pixel;
                 }
                 let mut color_num = get_bit_value(data2, tile_pixel) << 1;
                 color_num |= get_bit_value(data1, tile_pixel);
-
-                let color = self.map_color_pattel(color_num, palette);
                 // transparent
-                if color == White {
+                if color_num == 0 {
                     continue;
                 }
+                drawn[x_pix as usize] = true;
 
-                let x_pix = sprite_x + pixel as i32;
+                if self.bg_has_priority(x_pix as usize, attributes) {
+                    continue;
+                }
                 let current_pixel_address = self.ly as usize * SCREEN_WIDTH + x_pix as usize;
-                // if bit is set priority is behind 1-3
-                if check_bit(attributes, 7) {
-                    let current_pixel_color = self.screen_data[current_pixel_address];
-                    if current_pixel_color == 0x8bac0f
-                        || current_pixel_color == 0x306230
-                        || current_pixel_color == 0x0f380f
-                    {
-                        continue;
-                    }
+                if self.cgb {
+                    self.screen_data[current_pixel_address] =
+                        cgb_color(&self.obj_palette_ram, attributes & 0x7, color_num);
+                } else {
+                    let (palette, colors) = match check_bit(attributes, 4) {
+                        true => (self.obp1, self.palettes.obj1),
+                        false => (self.obp0, self.palettes.obj0),
+                    };
+                    let shade = self.map_color_pattel(color_num, palette);
+                    self.set_dmg_pixel(current_pixel_address, shade, colors);
                 }
-                self.screen_data[current_pixel_address] = color as u32;
             }
         }
     }
 
-    fn map_color_pattel(&self, color_number: u8, palette: u8) -> Color {
-        let mut hi_bit = 0;
-        let mut lo_bit = 0;
+    fn bg_has_priority(&self, pixel: usize, attributes: u8) -> bool {
+        if self.bg_color_numbers[pixel] == 0 {
+            return false;
+        }
+        // on cgb lcdc bit 0 clear puts sprites above everything
+        if self.cgb && (!self.bg_win_priority || !self.layers.cgb_priority) {
+            return false;
+        }
+        self.bg_attr_priority[pixel] || check_bit(attributes, 7)
+    }
 
-        match color_number {
-            0 => {
-                hi_bit = 1;
-                lo_bit = 0;
-            }
-            1 => {
-                hi_bit = 3;
-                lo_bit = 2;
-            }
-            2 => {
-                hi_bit = 5;
-                lo_bit = 4;
-            }
-            3 => {
-                hi_bit = 7;
-                lo_bit = 6;
-            }
-            _ => unreachable!(),
+    // maps a color number through a dmg palette register to a shade 0-3
+    fn map_color_pattel(&self, color_number: u8, palette: u8) -> u8 {
+        (palette >> (color_number * 2)) & 0x3
+    }
+
+    fn set_dmg_pixel(&mut self, address: usize, shade: u8, colors: Palette) {
+        self.shade_data[address] = shade;
+        self.screen_data[address] = colors.color(shade);
+    }
+
+    // the tile number and cgb attributes at a column and row of the tilemap at a vram offset
+    pub fn tilemap_entry(&self, tilemap: u16, column: u8, row: u8) -> (u8, u8) {
+        let address = tilemap as usize + row as usize * 32 + column as usize;
+        // cgb keeps the tile attributes in bank 1 of the tilemap
+        let attributes = if self.cgb { self.vram[VRAM_SIZE + address] } else { 0 };
+        (self.vram[address], attributes)
+    }
+
+    // the color number and cgb attributes of a pixel of a 256x256 tilemap
+    fn tilemap_pixel(&self, tilemap: u16, x: u8, y: u8) -> (u8, u8) {
+        let (tile_num, attributes) = self.tilemap_entry(tilemap, x / 8, y / 8);
+        let data_address = self.tile_data_address(tile_num);
+        let bank = if check_bit(attributes, 3) { VRAM_SIZE } else { 0 };
+
+        let mut tile_line = y % 8;
+        if check_bit(attributes, 6) {
+            tile_line = 7 - tile_line;
+        }
+        let line = (tile_line * 2) as u16;
+        let line_byte1 = self.vram[bank + (data_address + line) as usize];
+        let line_byte2 = self.vram[bank + (data_address + line + 1) as usize];
+        let mut color_bit = 7 - (x % 8);
+        if check_bit(attributes, 5) {
+            color_bit = x % 8;
+        }
+        let mut color_number = get_bit_value(line_byte2, color_bit) << 1;
+        color_number |= get_bit_value(line_byte1, color_bit);
+        (color_number, attributes)
+    }
+
+    // a pixel of a tilemap as the background would draw it, for debug views
+    pub fn tilemap_color(&self, tilemap: u16, x: u8, y: u8) -> u32 {
+        let (color_number, attributes) = self.tilemap_pixel(tilemap, x, y);
+        if self.cgb {
+            cgb_color(&self.bg_palette_ram, attributes & 0x7, color_number)
+        } else {
+            self.palettes.bg.color(self.map_color_pattel(color_number, self.bgp))
         }
+    }
 
-        let mut color = 0;
-        color = get_bit_value(palette, hi_bit) << 1;
-        color |= get_bit_value(palette, lo_bit);
+    // vram offset of a tile's data in the current addressing mode
+    pub fn tile_data_address(&self, tile_num: u8) -> u16 {
+        match self.tilebase {
+            0x0000 => self.tilebase + (tile_num as u16 * 16),
+            0x1000 => (self.tilebase as i32 + (tile_num as i8 as i16 as i32 * 16)) as u16,
+            _ => unreachable!("not a valid tile base"),
+        }
+    }
 
-        match color {
-            0 => White,
-            1 => LightGrey,
-            2 => DarkGrey,
-            _ => Black,
+    // sgb vram transfers read the first 256 tiles shown by the background map
+    pub fn vram_transfer_data(&self) -> Vec<u8> {
+        let mut data = Vec::with_capacity(0x1000);
+        for tile in 0..256 {
+            let tilemap_address = self.background_tilemap as usize + (tile / 20) * 32 + tile % 20;
+            let address = self.tile_data_address(self.vram[tilemap_address]) as usize;
+            data.extend_from_slice(&self.vram[address..address + 16]);
         }
+        data
     }
 
     pub fn dma_transfer(&mut self, address: u16, value: u8) {
         self.oam_ram[address as usize] = value;
     }
+
+    pub fn is_cgb(&self) -> bool {
+        self.cgb
+    }
+
+    // color numbers of a row of the tile at an offset into a vram bank, for debug views
+    pub fn tile_row(&self, bank: usize, address: usize, row: usize) -> [u8; 8] {
+        let data1 = self.vram[bank * VRAM_SIZE + address + row * 2];
+        let data2 = self.vram[bank * VRAM_SIZE + address + row * 2 + 1];
+        let mut colors = [0; 8];
+        for (pixel, color) in colors.iter_mut().enumerate() {
+            let bit = 7 - pixel as u8;
+            *color = get_bit_value(data2, bit) << 1 | get_bit_value(data1, bit);
+        }
+        colors
+    }
+
+    // the y, x, tile and attributes of an oam entry
+    pub fn oam_entry(&self, sprite: usize) -> [u8; 4] {
+        let mut entry = [0; 4];
+        entry.copy_from_slice(&self.oam_ram[sprite * 4..sprite * 4 + 4]);
+        entry
+    }
+
+    // the oam entries drawn on a line of the last frame, bit n for entry n
+    pub fn selected_sprites(&self, line: usize) -> u64 {
+        self.selected_sprites[line]
+    }
+
+    // what color numbers 0-3 of a sprite with these attributes look like
+    pub fn sprite_colors(&self, attributes: u8) -> [u32; 4] {
+        match (self.cgb, check_bit(attributes, 4)) {
+            (true, _) => {
+                let mut colors = [0; 4];
+                for (color_number, color) in colors.iter_mut().enumerate() {
+                    *color = cgb_color(&self.obj_palette_ram, attributes & 0x7, color_number as u8);
+                }
+                colors
+            }
+            (false, true) => self.debug_colors(DebugPalette::Obp1),
+            (false, false) => self.debug_colors(DebugPalette::Obp0),
+        }
+    }
+
+    // what color numbers 0-3 look like in a palette
+    pub fn debug_colors(&self, palette: DebugPalette) -> [u32; 4] {
+        let mut colors = [0; 4];
+        for (color_number, color) in colors.iter_mut().enumerate() {
+            let color_number = color_number as u8;
+            *color = match palette {
+                DebugPalette::Grey => Palette::GREY.color(color_number),
+                DebugPalette::Bgp if self.cgb => cgb_color(&self.bg_palette_ram, 0, color_number),
+                DebugPalette::Obp0 if self.cgb => cgb_color(&self.obj_palette_ram, 0, color_number),
+                DebugPalette::Obp1 if self.cgb => cgb_color(&self.obj_palette_ram, 1, color_number),
+                DebugPalette::Bgp => self.palettes.bg.color(self.map_color_pattel(color_number, self.bgp)),
+                DebugPalette::Obp0 => self.palettes.obj0.color(self.map_color_pattel(color_number, self.obp0)),
+                DebugPalette::Obp1 => self.palettes.obj1.color(self.map_color_pattel(color_number, self.obp1)),
+            };
+        }
+        colors
+    }
+}
+
+// bcps/ocps auto increment the index after a data write if bit 7 is set
+fn increment_palette_index(index: u8) -> u8 {
+    if check_bit(index, 7) {
+        0x80 | (index.wrapping_add(1) & 0x3f)
+    } else {
+        index
+    }
+}
+
+// cgb palettes hold 15 bit colors
+fn cgb_color(palette_ram: &[u8; PALETTE_RAM_SIZE], palette: u8, color_number: u8) -> u32 {
+    let index = (palette * 8 + color_number * 2) as usize;
+    rgb555_to_rgb888(palette_ram[index] as u16 | ((palette_ram[index + 1] as u16) << 8))
 }
@@ -0,0 +1,350 @@
+use crate::serial::Link;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::PathBuf;
+
+const WIDTH: usize = 160; // 20 tiles per row
+const BAND_SIZE: usize = 640; // one data packet, 2 rows of tiles
+const BAND_HEIGHT: usize = 16;
+const BUFFER_SIZE: usize = BAND_SIZE * 9; // the printer holds 9 bands
+const SHADES: [u8; 4] = [0xff, 0xaa, 0x55, 0x00];
+
+const INIT: u8 = 0x01;
+const PRINT: u8 = 0x02;
+const DATA: u8 = 0x04;
+const STATUS: u8 = 0x0f;
+
+// status bits
+const CHECKSUM_ERROR: u8 = 0x01;
+const BUSY: u8 = 0x02;
+const IMAGE_FULL: u8 = 0x04;
+const UNPROCESSED: u8 = 0x08;
+
+#[derive(Clone, Copy, PartialEq)]
+enum State {
+    Magic1,
+    Magic2,
+    Command,
+    Compression,
+    LengthLow,
+    LengthHigh,
+    Data,
+    ChecksumLow,
+    ChecksumHigh,
+    KeepAlive,
+    Status,
+}
+
+// game boy printer on the link port, every sheet it prints is saved as a png
+pub struct Printer {
+    state: State,
+    command: u8,
+    compressed: bool,
+    length: u16,
+    data: Vec<u8>,
+    checksum: u16,
+    received_checksum: u16,
+    status: u8,
+    busy: u8,       // status inquiries left until printing finishes
+    buffer: Vec<u8>, // tile data waiting to be printed
+    sheet: Vec<u8>,  // shades of the sheet being printed
+    directory: PathBuf,
+    printed: u32,
+}
+
+impl Printer {
+    pub fn new(directory: &str) -> Self {
+        Self {
+            state: State::Magic1,
+            command: 0,
+            compressed: false,
+            length: 0,
+            data: vec![],
+            checksum: 0,
+            received_checksum: 0,
+            status: 0,
+            busy: 0,
+            buffer: vec![],
+            sheet: vec![],
+            directory: PathBuf::from(directory),
+            printed: 0,
+        }
+    }
+
+    fn run_command(&mut self) {
+        if self.checksum != self.received_checksum {
+            self.status |= CHECKSUM_ERROR;
+            return;
+        }
+        self.status &= !CHECKSUM_ERROR;
+        match self.command {
+            INIT => {
+                self.buffer.clear();
+                self.status = 0;
+            }
+            DATA => {
+                let data = std::mem::take(&mut self.data);
+                if self.compressed {
+                    decompress(&data, &mut self.buffer);
+                } else {
+                    self.buffer.extend_from_slice(&data);
+                }
+                self.buffer.truncate(BUFFER_SIZE);
+                if !self.buffer.is_empty() {
+                    self.status |= UNPROCESSED;
+                }
+                if self.buffer.len() == BUFFER_SIZE {
+                    self.status |= IMAGE_FULL;
+                }
+            }
+            PRINT if self.data.len() >= 4 => {
+                let margins = self.data[1];
+                let palette = match self.data[2] {
+                    0 => 0xe4, // treated as the default palette
+                    n => n,
+                };
+                self.print(margins >> 4, margins & 0xf, palette);
+                self.status = (self.status & !(UNPROCESSED | IMAGE_FULL)) | BUSY;
+                self.busy = 4;
+            }
+            STATUS if self.busy > 0 => {
+                self.busy -= 1;
+                if self.busy == 0 {
+                    self.status &= !BUSY;
+                }
+            }
+            _ => (),
+        }
+    }
+
+    // margins are in bands of paper feed, a sheet only ends once there is a bottom margin
+    fn print(&mut self, top_margin: u8, bottom_margin: u8, palette: u8) {
+        self.feed(top_margin);
+        let buffer = std::mem::take(&mut self.buffer);
+        for band in buffer.chunks(BAND_SIZE) {
+            let start = self.sheet.len();
+            self.sheet.resize(start + WIDTH * BAND_HEIGHT, SHADES[0]);
+            for (tile, data) in band.chunks(16).enumerate() {
+                let tile_x = (tile % 20) * 8;
+                let tile_y = (tile / 20) * 8;
+                for line in 0..data.len() / 2 {
+                    for pixel in 0..8 {
+                        let bit = 7 - pixel;
+                        let color_number = ((data[line * 2] >> bit) & 0x1)
+                            | (((data[line * 2 + 1] >> bit) & 0x1) << 1);
+                        let shade = (palette >> (color_number * 2)) & 0x3;
+                        self.sheet[start + (tile_y + line) * WIDTH + tile_x + pixel] =
+                            SHADES[shade as usize];
+                    }
+                }
+            }
+        }
+        if bottom_margin > 0 {
+            self.feed(bottom_margin);
+            self.save_sheet();
+        }
+    }
+
+    fn feed(&mut self, bands: u8) {
+        let length = self.sheet.len() + bands as usize * WIDTH * BAND_HEIGHT;
+        self.sheet.resize(length, SHADES[0]);
+    }
+
+    fn save_sheet(&mut self) {
+        let sheet = std::mem::take(&mut self.sheet);
+        self.printed += 1;
+        let name = format!(
+            "print-{}-{}.png",
+            chrono::Local::now().format("%Y%m%d-%H%M%S"),
+            self.printed
+        );
+        let path = self.directory.join(name);
+        match write_png(&path, &sheet) {
+            Ok(_) => println!("printed {}", path.display()),
+            Err(e) => println!("could not save {}: {}", path.display(), e),
+        }
+    }
+}
+
+impl Link for Printer {
+    // the game boy always provides the clock, the printer answers each byte
+    fn transfer(&mut self, byte: u8) -> u8 {
+        let mut reply = 0;
+        self.state = match self.state {
+            State::Magic1 if byte == 0x88 => State::Magic2,
+            State::Magic1 => State::Magic1,
+            State::Magic2 if byte == 0x33 => State::Command,
+            State::Magic2 => State::Magic1,
+            State::Command => {
+                self.command = byte;
+                self.checksum = byte as u16;
+                self.data.clear();
+                State::Compression
+            }
+            State::Compression => {
+                self.compressed = byte & 0x1 == 0x1;
+                self.checksum = self.checksum.wrapping_add(byte as u16);
+                State::LengthLow
+            }
+            State::LengthLow => {
+                self.length = byte as u16;
+                self.checksum = self.checksum.wrapping_add(byte as u16);
+                State::LengthHigh
+            }
+            State::LengthHigh => {
+                self.length |= (byte as u16) << 8;
+                self.checksum = self.checksum.wrapping_add(byte as u16);
+                if self.length == 0 {
+                    State::ChecksumLow
+                } else {
+                    State::Data
+                }
+            }
+            State::Data => {
+                self.data.push(byte);
+                self.checksum = self.checksum.wrapping_add(byte as u16);
+                if self.data.len() == self.length as usize {
+                    State::ChecksumLow
+                } else {
+                    State::Data
+                }
+            }
+            State::ChecksumLow => {
+                self.received_checksum = byte as u16;
+                State::ChecksumHigh
+            }
+            State::ChecksumHigh => {
+                self.received_checksum |= (byte as u16) << 8;
+                State::KeepAlive
+            }
+            State::KeepAlive => {
+                reply = 0x81; // device id
+                self.run_command();
+                State::Status
+            }
+            State::Status => {
+                reply = self.status;
+                State::Magic1
+            }
+        };
+        reply
+    }
+
+    fn poll(&mut self, _byte: u8) -> Option<u8> {
+        None
+    }
+}
+
+// run length encoding, bit 7 set repeats the next byte (n & 0x7f) + 2 times,
+// otherwise n + 1 bytes follow as they are
+fn decompress(data: &[u8], output: &mut Vec<u8>) {
+    let mut i = 0;
+    while i < data.len() {
+        let control = data[i];
+        i += 1;
+        if control & 0x80 == 0x80 {
+            if let Some(&byte) = data.get(i) {
+                output.extend(std::iter::repeat_n(byte, (control & 0x7f) as usize + 2));
+            }
+            i += 1;
+        } else {
+            let end = (i + control as usize + 1).min(data.len());
+            output.extend_from_slice(&data[i..end]);
+            i = end;
+        }
+    }
+}
+
+fn write_png(path: &PathBuf, sheet: &[u8]) -> Result<(), png::EncodingError> {
+    let file = File::create(path)?;
+    let mut encoder = png::Encoder::new(BufWriter::new(file), WIDTH as u32, (sheet.len() / WIDTH) as u32);
+    encoder.set_color(png::ColorType::Grayscale);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(sheet)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // sends a whole packet, returns the device id and status the printer answered with
+    fn send(printer: &mut Printer, command: u8, compressed: bool, data: &[u8]) -> (u8, u8) {
+        let mut packet = vec![command, compressed as u8, data.len() as u8, (data.len() >> 8) as u8];
+        packet.extend_from_slice(data);
+        let checksum = packet.iter().fold(0u16, |sum, &byte| sum.wrapping_add(byte as u16));
+        packet.extend_from_slice(&checksum.to_le_bytes());
+
+        assert_eq!(printer.transfer(0x88), 0);
+        assert_eq!(printer.transfer(0x33), 0);
+        for byte in packet {
+            assert_eq!(printer.transfer(byte), 0);
+        }
+        (printer.transfer(0), printer.transfer(0))
+    }
+
+    #[test]
+    fn decompress_expands_runs_and_copies_literals() {
+        let mut output = vec![];
+        decompress(&[0x81, 0xaa, 0x02, 0x01, 0x02, 0x03, 0x80, 0xff], &mut output);
+        assert_eq!(output, [0xaa, 0xaa, 0xaa, 0x01, 0x02, 0x03, 0xff, 0xff]);
+    }
+
+    #[test]
+    fn decompress_stops_at_the_end_of_the_data() {
+        let mut output = vec![];
+        decompress(&[0x05, 0x01, 0x02, 0x85], &mut output);
+        assert_eq!(output, [0x01, 0x02, 0x85]);
+        output.clear();
+        decompress(&[0x01, 0x01, 0x85], &mut output);
+        assert_eq!(output, [0x01, 0x85]);
+    }
+
+    #[test]
+    fn packets_are_answered_with_the_device_id_and_status() {
+        let mut printer = Printer::new(".");
+        assert_eq!(send(&mut printer, INIT, false, &[]), (0x81, 0));
+        assert_eq!(send(&mut printer, DATA, false, &[0x12; 16]), (0x81, UNPROCESSED));
+        assert_eq!(printer.buffer, [0x12; 16]);
+        assert_eq!(send(&mut printer, STATUS, false, &[]), (0x81, UNPROCESSED));
+    }
+
+    #[test]
+    fn compressed_data_is_expanded_into_the_buffer() {
+        let mut printer = Printer::new(".");
+        send(&mut printer, INIT, false, &[]);
+        // runs of one byte and a literal
+        let mut data = vec![];
+        for _ in 0..5 {
+            data.extend_from_slice(&[0xff, 0x34]); // 0x7f + 2 = 129 bytes
+        }
+        data.extend_from_slice(&[0x81, 0x56, 0x00, 0x78]);
+        assert_eq!(send(&mut printer, DATA, true, &data), (0x81, UNPROCESSED));
+        assert_eq!(printer.buffer.len(), 129 * 5 + 3 + 1);
+        assert!(printer.buffer[..645].iter().all(|&byte| byte == 0x34));
+        assert_eq!(printer.buffer[645..], [0x56, 0x56, 0x56, 0x78]);
+    }
+
+    #[test]
+    fn a_bad_checksum_is_reported_and_ignored() {
+        let mut printer = Printer::new(".");
+        for byte in [0x88, 0x33, DATA, 0, 1, 0, 0x12, 0, 0] {
+            printer.transfer(byte);
+        }
+        assert_eq!((printer.transfer(0), printer.transfer(0)), (0x81, CHECKSUM_ERROR));
+        assert!(printer.buffer.is_empty());
+    }
+
+    #[test]
+    fn printing_keeps_the_printer_busy_for_a_few_inquiries() {
+        let mut printer = Printer::new(".");
+        send(&mut printer, DATA, false, &[0; BAND_SIZE]);
+        // no bottom margin, so nothing is saved yet
+        assert_eq!(send(&mut printer, PRINT, false, &[1, 0x10, 0xe4, 0x40]), (0x81, BUSY));
+        assert!(printer.buffer.is_empty());
+        for _ in 0..3 {
+            assert_eq!(send(&mut printer, STATUS, false, &[]), (0x81, BUSY));
+        }
+        assert_eq!(send(&mut printer, STATUS, false, &[]), (0x81, 0));
+    }
+}
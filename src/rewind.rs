@@ -0,0 +1,182 @@
+use crate::input::FrameInput;
+use std::collections::VecDeque;
+
+// a save state is taken every few frames and the input of the frames in between
+// are kept, so any frame can be rebuilt by loading a snapshot and running it forward.
+// only the newest snapshot is kept whole, older ones are stored as the
+// run length encoded xor against the next newer one
+pub struct RewindBuffer {
+    interval: usize,  // frames between snapshots
+    capacity: usize,  // snapshots kept, the oldest are dropped
+    latest: Option<Vec<u8>>,
+    latest_frames: Vec<FrameInput>, // input of the frames run since the newest snapshot
+    older: VecDeque<(Vec<u8>, Vec<FrameInput>)>, // delta and frames of each older snapshot, oldest first
+}
+
+impl RewindBuffer {
+    pub fn new(interval: usize, capacity: usize) -> Self {
+        Self {
+            interval,
+            capacity,
+            latest: None,
+            latest_frames: vec![],
+            older: VecDeque::new(),
+        }
+    }
+
+    pub fn snapshot_due(&self) -> bool {
+        self.latest.is_none() || self.latest_frames.len() >= self.interval
+    }
+
+    pub fn push_snapshot(&mut self, state: Vec<u8>) {
+        if let Some(latest) = self.latest.take() {
+            let frames = std::mem::take(&mut self.latest_frames);
+            self.older.push_back((encode_delta(&state, &latest), frames));
+            if self.older.len() >= self.capacity {
+                self.older.pop_front();
+            }
+        }
+        self.latest = Some(state);
+    }
+
+    // forgets everything, for when the machine jumps to a state the snapshots don't lead to
+    pub fn clear(&mut self) {
+        self.latest = None;
+        self.latest_frames.clear();
+        self.older.clear();
+    }
+
+    // called at the end of every frame, snapshots are taken before a frame starts
+    pub fn record_frame(&mut self, input: FrameInput) {
+        self.latest_frames.push(input);
+    }
+
+    // forgets the newest frame, returns the snapshot to load and the input
+    // of the frames to run after it, none when there is nothing left
+    pub fn step_back(&mut self) -> Option<(Vec<u8>, Vec<FrameInput>)> {
+        // the oldest frame that can be drawn is the one after the oldest snapshot
+        if self.latest_frames.len() <= 1 && self.older.is_empty() {
+            return None;
+        }
+        if self.latest_frames.is_empty() {
+            self.step_into_older();
+        }
+        self.latest_frames.pop();
+        // a snapshot with no frames after it has nothing to draw, the frames
+        // leading up to it are run from the one before instead
+        if self.latest_frames.is_empty() && !self.older.is_empty() {
+            self.step_into_older();
+        }
+        Some((self.latest.clone()?, self.latest_frames.clone()))
+    }
+
+    // makes the next older snapshot the newest, with the frames that led to the dropped one
+    fn step_into_older(&mut self) {
+        if let (Some((delta, frames)), Some(latest)) = (self.older.pop_back(), &self.latest) {
+            self.latest = Some(decode_delta(latest, &delta));
+            self.latest_frames = frames;
+        }
+    }
+}
+
+// the xor of two snapshots is mostly zeros, it is stored as pairs of a zero
+// run length and a literal count followed by the literals, runs are at most 255.
+// states can differ in length, the missing bytes of the base count as zeros
+fn encode_delta(base: &[u8], target: &[u8]) -> Vec<u8> {
+    let mut delta = (target.len() as u32).to_le_bytes().to_vec();
+    let xor: Vec<u8> = target
+        .iter()
+        .enumerate()
+        .map(|(i, byte)| byte ^ base.get(i).unwrap_or(&0))
+        .collect();
+    let mut i = 0;
+    while i < xor.len() {
+        let zeros = xor[i..].iter().take(255).take_while(|byte| **byte == 0).count();
+        i += zeros;
+        let literals = xor[i..].iter().take(255).take_while(|byte| **byte != 0).count();
+        delta.push(zeros as u8);
+        delta.push(literals as u8);
+        delta.extend_from_slice(&xor[i..i + literals]);
+        i += literals;
+    }
+    delta
+}
+
+fn decode_delta(base: &[u8], delta: &[u8]) -> Vec<u8> {
+    let length = u32::from_le_bytes([delta[0], delta[1], delta[2], delta[3]]) as usize;
+    let mut target = Vec::with_capacity(length);
+    let mut i = 4;
+    while i < delta.len() {
+        let zeros = delta[i] as usize;
+        let literals = delta[i + 1] as usize;
+        i += 2;
+        for _ in 0..zeros {
+            target.push(*base.get(target.len()).unwrap_or(&0));
+        }
+        for byte in &delta[i..i + literals] {
+            target.push(byte ^ base.get(target.len()).unwrap_or(&0));
+        }
+        i += literals;
+    }
+    target
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(base: &[u8], target: &[u8]) {
+        assert_eq!(decode_delta(base, &encode_delta(base, target)), target);
+    }
+
+    #[test]
+    fn deltas_round_trip() {
+        let base: Vec<u8> = (0..1000).map(|i| (i * 7) as u8).collect();
+        let mut target = base.clone();
+        target[3] ^= 0x55;
+        target[600] = 0;
+        round_trip(&base, &target);
+        round_trip(&base, &base);
+        round_trip(&base, &[]);
+        round_trip(&[], &base);
+    }
+
+    #[test]
+    fn deltas_of_different_lengths_round_trip() {
+        let base: Vec<u8> = (0..300).map(|i| i as u8).collect();
+        let mut longer = base.clone();
+        longer.extend_from_slice(&[1, 2, 3, 0, 0, 4]);
+        round_trip(&base, &longer);
+        round_trip(&base, &base[..123]);
+        round_trip(&longer, &base);
+    }
+
+    #[test]
+    fn long_runs_round_trip() {
+        let base = vec![0xaa; 2000];
+        let mut target = base.clone();
+        target[1500] = 0x01; // a zero run of 1500 in the xor
+        for byte in &mut target[100..700] {
+            *byte = 0x55; // and a literal run of 600
+        }
+        round_trip(&base, &target);
+        round_trip(&vec![0; 700], &vec![0; 700]);
+        round_trip(&vec![0; 256], &vec![0xff; 512]);
+    }
+
+    #[test]
+    fn stepping_back_past_a_snapshot_runs_the_frames_before_it() {
+        let mut rewind = RewindBuffer::new(2, 10);
+        let frame = |buttons: u8| vec![(0, buttons)];
+        rewind.push_snapshot(vec![1; 8]);
+        rewind.record_frame(frame(1));
+        rewind.record_frame(frame(2));
+        rewind.push_snapshot(vec![2; 8]);
+        rewind.record_frame(frame(3));
+
+        // back to the start of frame 3 is the state after frames 1 and 2
+        assert_eq!(rewind.step_back(), Some((vec![1; 8], vec![frame(1), frame(2)])));
+        assert_eq!(rewind.step_back(), Some((vec![1; 8], vec![frame(1)])));
+        assert_eq!(rewind.step_back(), None);
+    }
+}
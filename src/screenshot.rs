@@ -0,0 +1,45 @@
+use std::fs::File;
+use std::io::BufReader;
+use std::io::BufWriter;
+
+// saves a frame of 0xrrggbb pixels as an rgb png, each pixel drawn as a scale by scale square
+pub fn save_png(path: &str, buffer: &[u32], width: usize, height: usize, scale: usize) -> Result<(), String> {
+    let error = |e: &dyn std::fmt::Display| format!("{}: {}", path, e);
+    let file = File::create(path).map_err(|e| error(&e))?;
+    let mut encoder = png::Encoder::new(BufWriter::new(file), (width * scale) as u32, (height * scale) as u32);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header().map_err(|e| error(&e))?;
+    let mut data = Vec::with_capacity(width * height * scale * scale * 3);
+    for row in buffer.chunks(width) {
+        for _ in 0..scale {
+            for pixel in row {
+                for _ in 0..scale {
+                    data.extend_from_slice(&pixel.to_be_bytes()[1..]);
+                }
+            }
+        }
+    }
+    writer.write_image_data(&data).map_err(|e| error(&e))
+}
+
+// reads a png back as 0xrrggbb pixels with its width and height, any transparency is dropped
+pub fn load_png(path: &str) -> Result<(Vec<u32>, usize, usize), String> {
+    let error = |e: &dyn std::fmt::Display| format!("{}: {}", path, e);
+    let file = File::open(path).map_err(|e| error(&e))?;
+    let mut decoder = png::Decoder::new(BufReader::new(file));
+    // palettes and low bit depths come out as 8 bit grey or rgb
+    decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+    let mut reader = decoder.read_info().map_err(|e| error(&e))?;
+    let mut data = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut data).map_err(|e| error(&e))?;
+    let channels = info.color_type.samples();
+    let pixels = data[..info.buffer_size()]
+        .chunks(channels)
+        .map(|pixel| match pixel.len() {
+            1 | 2 => u32::from_be_bytes([0, pixel[0], pixel[0], pixel[0]]),
+            _ => u32::from_be_bytes([0, pixel[0], pixel[1], pixel[2]]),
+        })
+        .collect();
+    Ok((pixels, info.width as usize, info.height as usize))
+}
@@ -1,32 +1,178 @@
+use crate::state::{StateReader, StateWriter};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+// the other end of the link cable
+pub trait Link {
+    // we provide the clock, sends our byte and returns the byte shifted in
+    fn transfer(&mut self, byte: u8) -> u8;
+    // the other side provides the clock, gives it our byte and returns its byte
+    // once it has clocked a transfer
+    fn poll(&mut self, byte: u8) -> Option<u8>;
+}
+
+// nothing plugged in, the input line floats high and nobody provides a clock
+pub struct Disconnected;
+
+impl Link for Disconnected {
+    fn transfer(&mut self, _byte: u8) -> u8 {
+        0xff
+    }
+
+    fn poll(&mut self, _byte: u8) -> Option<u8> {
+        None
+    }
+}
+
+// output wired to input, every byte comes back
+pub struct Loopback;
+
+impl Link for Loopback {
+    fn transfer(&mut self, byte: u8) -> u8 {
+        byte
+    }
+
+    fn poll(&mut self, _byte: u8) -> Option<u8> {
+        None
+    }
+}
+
+// prints every byte sent, test roms report their results this way
+pub struct Stdout;
+
+impl Link for Stdout {
+    fn transfer(&mut self, byte: u8) -> u8 {
+        print!("{}", byte as char);
+        0xff
+    }
+
+    fn poll(&mut self, _byte: u8) -> Option<u8> {
+        None
+    }
+}
+
+// keeps every byte sent so test rom results can be checked
+pub struct Capture(pub Rc<RefCell<Vec<u8>>>);
+
+impl Link for Capture {
+    fn transfer(&mut self, byte: u8) -> u8 {
+        self.0.borrow_mut().push(byte);
+        0xff
+    }
+
+    fn poll(&mut self, _byte: u8) -> Option<u8> {
+        None
+    }
+}
+
 pub struct Serial {
     sb: u8,
     sc: u8,
+    cgb: bool,
+    link: Box<dyn Link>,
+    incoming: u8, // byte being shifted in
+    bits_left: u8,
+    clocks: u32,
+    pub interrupt: u8,
 }
 
 impl Serial {
-    pub fn new() -> Self{
+    pub fn new(cgb: bool) -> Self{
         Self {
             sb: 0,
             sc: 0,
+            cgb,
+            link: Box::new(Disconnected),
+            incoming: 0xff,
+            bits_left: 0,
+            clocks: 0,
+            interrupt: 0,
         }
     }
 
+    pub fn connect(&mut self, link: Box<dyn Link>) {
+        self.link = link;
+    }
+
+    // unplugs the link, leaving nothing connected
+    pub fn disconnect(&mut self) -> Box<dyn Link> {
+        std::mem::replace(&mut self.link, Box::new(Disconnected))
+    }
+
+    // the link itself stays connected
+    pub fn save_state(&self, state: &mut StateWriter) {
+        state.write_u8(self.sb);
+        state.write_u8(self.sc);
+        state.write_u8(self.incoming);
+        state.write_u8(self.bits_left);
+        state.write_u32(self.clocks);
+    }
+
+    pub fn load_state(&mut self, state: &mut StateReader) -> Result<(), String> {
+        self.sb = state.read_u8()?;
+        self.sc = state.read_u8()?;
+        self.incoming = state.read_u8()?;
+        self.bits_left = state.read_u8()?;
+        self.clocks = state.read_u32()?;
+        Ok(())
+    }
+
     pub fn read_byte(&self, address: u16) -> u8 {
         match address {
             0xff01 => self.sb,
-            0xff02 => self.sc,
+            // unused bits read as 1, bit 1 is the cgb clock speed
+            0xff02 => if self.cgb { 0x7c | self.sc } else { 0x7e | self.sc },
             n => unreachable!("address {:#x} is not handled by serial", n)
         }
     }
 
     pub fn write_byte(&mut self, address: u16, value: u8){
-        if address == 0xFF02 && value == 0x81 {
-            print!("{}", self.read_byte(0xff01) as char);
-        }
         match address {
             0xff01 => self.sb = value,
-            0xff02 => self.sc = value,
+            0xff02 => {
+                self.sc = if self.cgb { value & 0x83 } else { value & 0x81 };
+                // with the internal clock the byte is exchanged up front and shifted in over 8 bits
+                if self.sc & 0x81 == 0x81 {
+                    self.incoming = self.link.transfer(self.sb);
+                    self.bits_left = 8;
+                    self.clocks = 0;
+                } else {
+                    self.bits_left = 0;
+                }
+            }
             n => unreachable!("address {:#x} is not handled by serial", n)
         }
     }
-}
\ No newline at end of file
+
+    pub fn tick(&mut self, clocks: u32) {
+        self.interrupt = 0; // reset interrupt
+        if self.bits_left == 0 {
+            // the other side can clock a transfer whenever we aren't, it only
+            // completes ours if we are waiting on an external clock
+            if let Some(byte) = self.link.poll(self.sb) {
+                if self.sc & 0x81 == 0x80 {
+                    self.sb = byte;
+                    self.finish_transfer();
+                }
+            }
+            return;
+        }
+
+        // 8192hz, or 262144hz with the cgb fast clock
+        let clocks_per_bit = if self.sc & 0x2 == 0x2 { 16 } else { 512 };
+        self.clocks += clocks;
+        while self.clocks >= clocks_per_bit && self.bits_left > 0 {
+            self.clocks -= clocks_per_bit;
+            self.bits_left -= 1;
+            self.sb = (self.sb << 1) | ((self.incoming >> self.bits_left) & 0x1);
+            if self.bits_left == 0 {
+                self.finish_transfer();
+            }
+        }
+    }
+
+    fn finish_transfer(&mut self) {
+        self.sc &= 0x7f;
+        self.interrupt = 0x8;
+    }
+}
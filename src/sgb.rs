@@ -0,0 +1,568 @@
+use crate::state::{StateReader, StateWriter};
+use crate::utills::{check_bit, rgb555_to_rgb888};
+const SCREEN_WIDTH: usize = 160;
+const SCREEN_HEIGHT: usize = 144;
+pub const BORDER_WIDTH: usize = 256;
+pub const BORDER_HEIGHT: usize = 224;
+const ATTRIBUTE_WIDTH: usize = 20; // attributes apply to 8x8 cells of the screen
+const ATTRIBUTE_HEIGHT: usize = 18;
+const ATTRIBUTE_FILE_SIZE: usize = 90;
+const PACKET_SIZE: usize = 16;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Mask {
+    Cancel,
+    Freeze,
+    Black,
+    Color0,
+}
+
+#[derive(Clone, Copy)]
+enum Transfer {
+    Palettes,
+    Attributes,
+    BorderTiles(bool), // upper half of the tiles
+    BorderMap,
+}
+
+pub struct Sgb {
+    previous_p1: u8,
+    receiving: bool,
+    packet: [u8; PACKET_SIZE],
+    bit_index: usize,
+    data: Vec<u8>,     // packets of the current command
+    packets_left: usize,
+    palettes: [[u16; 4]; 4],
+    system_palettes: Vec<u16>, // 512 palettes sent with pal_trn
+    attribute_map: [u8; ATTRIBUTE_WIDTH * ATTRIBUTE_HEIGHT],
+    attribute_files: Vec<u8>, // 45 files sent with attr_trn
+    transfer: Option<Transfer>,
+    border_tiles: Vec<u8>, // 256 snes 4bpp tiles
+    border_map: Vec<u8>,
+    border_palettes: [[u16; 16]; 4], // palettes 4-7
+    mask: Mask,
+    frame: Vec<u32>, // last unmasked frame, shown while frozen
+    players: u8,
+    player: u8,
+}
+
+impl Sgb {
+    pub fn new() -> Self {
+        Self {
+            previous_p1: 0x30,
+            receiving: false,
+            packet: [0; PACKET_SIZE],
+            bit_index: 0,
+            data: vec![],
+            packets_left: 0,
+            palettes: [[0x7fff, 0x56b5, 0x294a, 0x0000]; 4],
+            system_palettes: vec![0; 512 * 4],
+            attribute_map: [0; ATTRIBUTE_WIDTH * ATTRIBUTE_HEIGHT],
+            attribute_files: vec![0; 45 * ATTRIBUTE_FILE_SIZE],
+            transfer: None,
+            border_tiles: vec![0; 256 * 32],
+            border_map: vec![0; 0x800],
+            border_palettes: [[0; 16]; 4],
+            mask: Mask::Cancel,
+            frame: vec![0; SCREEN_WIDTH * SCREEN_HEIGHT],
+            players: 1,
+            player: 0,
+        }
+    }
+
+    pub fn save_state(&self, state: &mut StateWriter) {
+        state.write_u8(self.previous_p1);
+        state.write_bool(self.receiving);
+        state.write_bytes(&self.packet);
+        state.write_u32(self.bit_index as u32);
+        state.write_vec(&self.data);
+        state.write_u32(self.packets_left as u32);
+        for color in self.palettes.iter().flatten() {
+            state.write_u16(*color);
+        }
+        for color in self.system_palettes.iter() {
+            state.write_u16(*color);
+        }
+        state.write_bytes(&self.attribute_map);
+        state.write_bytes(&self.attribute_files);
+        state.write_u8(match self.transfer {
+            None => 0,
+            Some(Transfer::Palettes) => 1,
+            Some(Transfer::Attributes) => 2,
+            Some(Transfer::BorderTiles(false)) => 3,
+            Some(Transfer::BorderTiles(true)) => 4,
+            Some(Transfer::BorderMap) => 5,
+        });
+        state.write_bytes(&self.border_tiles);
+        state.write_bytes(&self.border_map);
+        for color in self.border_palettes.iter().flatten() {
+            state.write_u16(*color);
+        }
+        state.write_u8(self.mask as u8);
+        for pixel in self.frame.iter() {
+            state.write_u32(*pixel);
+        }
+        state.write_u8(self.players);
+        state.write_u8(self.player);
+    }
+
+    pub fn load_state(&mut self, state: &mut StateReader) -> Result<(), String> {
+        self.previous_p1 = state.read_u8()?;
+        self.receiving = state.read_bool()?;
+        state.read_bytes(&mut self.packet)?;
+        self.bit_index = (state.read_u32()? as usize).min(PACKET_SIZE * 8 - 1);
+        self.data = state.read_vec()?;
+        self.packets_left = state.read_u32()? as usize;
+        for color in self.palettes.iter_mut().flatten() {
+            *color = state.read_u16()?;
+        }
+        for color in self.system_palettes.iter_mut() {
+            *color = state.read_u16()?;
+        }
+        state.read_bytes(&mut self.attribute_map)?;
+        state.read_bytes(&mut self.attribute_files)?;
+        self.transfer = match state.read_u8()? {
+            1 => Some(Transfer::Palettes),
+            2 => Some(Transfer::Attributes),
+            3 => Some(Transfer::BorderTiles(false)),
+            4 => Some(Transfer::BorderTiles(true)),
+            5 => Some(Transfer::BorderMap),
+            _ => None,
+        };
+        state.read_bytes(&mut self.border_tiles)?;
+        state.read_bytes(&mut self.border_map)?;
+        for color in self.border_palettes.iter_mut().flatten() {
+            *color = state.read_u16()?;
+        }
+        self.mask = match state.read_u8()? {
+            1 => Mask::Freeze,
+            2 => Mask::Black,
+            3 => Mask::Color0,
+            _ => Mask::Cancel,
+        };
+        for pixel in self.frame.iter_mut() {
+            *pixel = state.read_u32()?;
+        }
+        self.players = state.read_u8()?;
+        self.player = state.read_u8()?;
+        Ok(())
+    }
+
+    // packets are sent one bit per write to p1, p14 low is a 0 and p15 low is a 1
+    pub fn write_joypad(&mut self, value: u8) {
+        let p1 = value & 0x30;
+        let previous_p1 = self.previous_p1;
+        self.previous_p1 = p1;
+        match p1 {
+            // both lines low resets the transfer
+            0x00 => {
+                self.receiving = true;
+                self.bit_index = 0;
+                self.packet = [0; PACKET_SIZE];
+            }
+            0x10 | 0x20 if previous_p1 == 0x30 && self.receiving => {
+                if p1 == 0x10 {
+                    self.packet[self.bit_index / 8] |= 1 << (self.bit_index % 8);
+                }
+                self.bit_index += 1;
+                // the stop bit after the 128th bit is ignored
+                if self.bit_index == PACKET_SIZE * 8 {
+                    self.receiving = false;
+                    self.receive_packet();
+                }
+            }
+            // multiplayer moves to the next joypad when p15 goes high
+            0x30 if !self.receiving && previous_p1 & 0x20 == 0 && self.players > 1 => {
+                self.player = (self.player + 1) % self.players;
+            }
+            _ => (),
+        }
+    }
+
+    pub fn read_joypad(&self, value: u8) -> u8 {
+        if self.players == 1 {
+            value
+        } else if value & 0x30 == 0x30 {
+            (value & 0xf0) | (0xf - self.player)
+        } else if self.player != 0 {
+            value | 0x0f // only the first joypad is connected
+        } else {
+            value
+        }
+    }
+
+    fn receive_packet(&mut self) {
+        if self.packets_left == 0 {
+            self.data.clear();
+            self.packets_left = (self.packet[0] & 0x7) as usize;
+            if self.packets_left == 0 {
+                return;
+            }
+        }
+        self.data.extend_from_slice(&self.packet);
+        self.packets_left -= 1;
+        if self.packets_left == 0 {
+            self.run_command();
+        }
+    }
+
+    fn run_command(&mut self) {
+        let data = std::mem::take(&mut self.data);
+        match data[0] >> 3 {
+            0x00 => self.set_palettes(&data, 0, 1),  // PAL01
+            0x01 => self.set_palettes(&data, 2, 3),  // PAL23
+            0x02 => self.set_palettes(&data, 0, 3),  // PAL03
+            0x03 => self.set_palettes(&data, 1, 2),  // PAL12
+            0x04 => self.attribute_block(&data),     // ATTR_BLK
+            0x05 => self.attribute_line(&data),      // ATTR_LIN
+            0x06 => self.attribute_divide(&data),    // ATTR_DIV
+            0x07 => self.attribute_characters(&data), // ATTR_CHR
+            0x0a => self.set_system_palettes(&data), // PAL_SET
+            0x0b => self.transfer = Some(Transfer::Palettes), // PAL_TRN
+            0x11 => {
+                // MLT_REQ
+                self.players = match data[1] & 0x3 {
+                    1 => 2,
+                    3 => 4,
+                    _ => 1,
+                };
+                self.player = 0;
+            }
+            0x13 => self.transfer = Some(Transfer::BorderTiles(check_bit(data[1], 0))), // CHR_TRN
+            0x14 => self.transfer = Some(Transfer::BorderMap), // PCT_TRN
+            0x15 => self.transfer = Some(Transfer::Attributes), // ATTR_TRN
+            0x16 => {
+                // ATTR_SET
+                self.apply_attribute_file(data[1] & 0x3f);
+                if check_bit(data[1], 6) {
+                    self.mask = Mask::Cancel;
+                }
+            }
+            0x17 => {
+                // MASK_EN
+                self.mask = match data[1] & 0x3 {
+                    0 => Mask::Cancel,
+                    1 => Mask::Freeze,
+                    2 => Mask::Black,
+                    _ => Mask::Color0,
+                }
+            }
+            _ => (), // sound and snes commands aren't emulated
+        }
+    }
+
+    fn set_palettes(&mut self, data: &[u8], first: usize, second: usize) {
+        let color = |index: usize| data[1 + index * 2] as u16 | ((data[2 + index * 2] as u16) << 8);
+        // color 0 is shared by all palettes
+        for palette in self.palettes.iter_mut() {
+            palette[0] = color(0);
+        }
+        for i in 1..4 {
+            self.palettes[first][i] = color(i);
+            self.palettes[second][i] = color(i + 3);
+        }
+    }
+
+    fn set_system_palettes(&mut self, data: &[u8]) {
+        for palette in 0..4 {
+            let id = (data[1 + palette * 2] as usize | ((data[2 + palette * 2] as usize) << 8)) & 0x1ff;
+            for i in 0..4 {
+                self.palettes[palette][i] = self.system_palettes[id * 4 + i];
+            }
+        }
+        let color0 = self.palettes[0][0];
+        for palette in self.palettes.iter_mut() {
+            palette[0] = color0;
+        }
+        if check_bit(data[9], 7) {
+            self.apply_attribute_file(data[9] & 0x3f);
+        }
+        if check_bit(data[9], 6) {
+            self.mask = Mask::Cancel;
+        }
+    }
+
+    fn attribute_block(&mut self, data: &[u8]) {
+        let count = (data[1] & 0x1f) as usize;
+        for set in data[2..].chunks(6).take(count) {
+            if set.len() < 6 {
+                break;
+            }
+            let control = set[0] & 0x7;
+            let inside = set[1] & 0x3;
+            let mut border = (set[1] >> 2) & 0x3;
+            let outside = (set[1] >> 4) & 0x3;
+            // changing only the inside or outside also changes the border
+            match control {
+                0x1 => border = inside,
+                0x4 => border = outside,
+                _ => (),
+            }
+            let change_border = control & 0x2 != 0 || control == 0x1 || control == 0x4;
+            let (x1, y1) = ((set[2] & 0x1f) as usize, (set[3] & 0x1f) as usize);
+            let (x2, y2) = ((set[4] & 0x1f) as usize, (set[5] & 0x1f) as usize);
+            for y in 0..ATTRIBUTE_HEIGHT {
+                for x in 0..ATTRIBUTE_WIDTH {
+                    let in_block = x >= x1 && x <= x2 && y >= y1 && y <= y2;
+                    let on_border = in_block && (x == x1 || x == x2 || y == y1 || y == y2);
+                    let palette = if on_border {
+                        if change_border { Some(border) } else { None }
+                    } else if in_block {
+                        if check_bit(control, 0) { Some(inside) } else { None }
+                    } else if check_bit(control, 2) {
+                        Some(outside)
+                    } else {
+                        None
+                    };
+                    if let Some(palette) = palette {
+                        self.attribute_map[y * ATTRIBUTE_WIDTH + x] = palette;
+                    }
+                }
+            }
+        }
+    }
+
+    fn attribute_line(&mut self, data: &[u8]) {
+        let count = data[1] as usize;
+        for &line in data[2..].iter().take(count) {
+            let number = (line & 0x1f) as usize;
+            let palette = (line >> 5) & 0x3;
+            if check_bit(line, 7) {
+                if number < ATTRIBUTE_HEIGHT {
+                    for x in 0..ATTRIBUTE_WIDTH {
+                        self.attribute_map[number * ATTRIBUTE_WIDTH + x] = palette;
+                    }
+                }
+            } else if number < ATTRIBUTE_WIDTH {
+                for y in 0..ATTRIBUTE_HEIGHT {
+                    self.attribute_map[y * ATTRIBUTE_WIDTH + number] = palette;
+                }
+            }
+        }
+    }
+
+    fn attribute_divide(&mut self, data: &[u8]) {
+        let after = data[1] & 0x3;
+        let before = (data[1] >> 2) & 0x3;
+        let on_line = (data[1] >> 4) & 0x3;
+        let horizontal = check_bit(data[1], 6);
+        let line = (data[2] & 0x1f) as usize;
+        for y in 0..ATTRIBUTE_HEIGHT {
+            for x in 0..ATTRIBUTE_WIDTH {
+                let position = if horizontal { y } else { x };
+                self.attribute_map[y * ATTRIBUTE_WIDTH + x] = if position < line {
+                    before
+                } else if position == line {
+                    on_line
+                } else {
+                    after
+                };
+            }
+        }
+    }
+
+    fn attribute_characters(&mut self, data: &[u8]) {
+        let mut x = (data[1] & 0x1f) as usize;
+        let mut y = (data[2] & 0x1f) as usize;
+        let count = data[3] as usize | ((data[4] as usize) << 8);
+        let vertical = check_bit(data[5], 0);
+        for i in 0..count.min(ATTRIBUTE_WIDTH * ATTRIBUTE_HEIGHT) {
+            let byte = match data.get(6 + i / 4) {
+                Some(byte) => *byte,
+                None => break,
+            };
+            if x < ATTRIBUTE_WIDTH && y < ATTRIBUTE_HEIGHT {
+                self.attribute_map[y * ATTRIBUTE_WIDTH + x] = (byte >> (6 - (i % 4) * 2)) & 0x3;
+            }
+            if vertical {
+                y += 1;
+                if y == ATTRIBUTE_HEIGHT {
+                    y = 0;
+                    x += 1;
+                }
+            } else {
+                x += 1;
+                if x == ATTRIBUTE_WIDTH {
+                    x = 0;
+                    y += 1;
+                }
+            }
+        }
+    }
+
+    fn apply_attribute_file(&mut self, file: u8) {
+        let file = file as usize;
+        if file >= 45 {
+            return;
+        }
+        let data = &self.attribute_files[file * ATTRIBUTE_FILE_SIZE..(file + 1) * ATTRIBUTE_FILE_SIZE];
+        for (i, cell) in self.attribute_map.iter_mut().enumerate() {
+            *cell = (data[i / 4] >> (6 - (i % 4) * 2)) & 0x3;
+        }
+    }
+
+    pub fn transfer_pending(&self) -> bool {
+        self.transfer.is_some()
+    }
+
+    // vram transfers take the 4kb of tile data displayed on the next frame
+    pub fn complete_transfer(&mut self, data: &[u8]) {
+        let word = |index: usize| data[index * 2] as u16 | ((data[index * 2 + 1] as u16) << 8);
+        match self.transfer.take() {
+            Some(Transfer::Palettes) => {
+                for (i, color) in self.system_palettes.iter_mut().enumerate() {
+                    *color = word(i);
+                }
+            }
+            Some(Transfer::Attributes) => {
+                let size = self.attribute_files.len();
+                self.attribute_files.copy_from_slice(&data[..size]);
+            }
+            Some(Transfer::BorderTiles(upper)) => {
+                let offset = if upper { 0x1000 } else { 0 };
+                self.border_tiles[offset..offset + 0x1000].copy_from_slice(&data[..0x1000]);
+            }
+            Some(Transfer::BorderMap) => {
+                self.border_map.copy_from_slice(&data[..0x800]);
+                for palette in 0..4 {
+                    for i in 0..16 {
+                        self.border_palettes[palette][i] = word(0x400 + palette * 16 + i);
+                    }
+                }
+            }
+            None => (),
+        }
+    }
+
+    // applies the sgb palettes to a finished frame of dmg shades
+    pub fn colorize(&mut self, shades: &[u8], screen: &mut [u32]) {
+        match self.mask {
+            Mask::Cancel => {
+                for (i, pixel) in screen.iter_mut().enumerate() {
+                    let (x, y) = (i % SCREEN_WIDTH, i / SCREEN_WIDTH);
+                    let palette = self.attribute_map[(y / 8) * ATTRIBUTE_WIDTH + x / 8] as usize;
+                    *pixel = rgb555_to_rgb888(self.palettes[palette][shades[i] as usize]);
+                }
+                self.frame.copy_from_slice(screen);
+            }
+            Mask::Freeze => screen.copy_from_slice(&self.frame),
+            Mask::Black => screen.iter_mut().for_each(|pixel| *pixel = 0),
+            Mask::Color0 => {
+                let color = rgb555_to_rgb888(self.palettes[0][0]);
+                screen.iter_mut().for_each(|pixel| *pixel = color);
+            }
+        }
+    }
+
+    // draws the border around the 160x144 screen into a 256x224 frame, the
+    // frame's buffer is reused so nothing is allocated once it has grown
+    pub fn draw_border(&self, frame: &mut Vec<u32>, screen: &[u32]) {
+        frame.clear();
+        frame.resize(BORDER_WIDTH * BORDER_HEIGHT, rgb555_to_rgb888(self.palettes[0][0]));
+        draw_screen(frame, screen);
+
+        for tile_y in 0..BORDER_HEIGHT / 8 {
+            for tile_x in 0..BORDER_WIDTH / 8 {
+                let index = (tile_y * 32 + tile_x) * 2;
+                let entry = self.border_map[index] as u16 | ((self.border_map[index + 1] as u16) << 8);
+                let tile = (entry & 0xff) as usize;
+                let palette = ((entry >> 10) & 0x3) as usize;
+                let x_flip = entry & 0x4000 != 0;
+                let y_flip = entry & 0x8000 != 0;
+                let data = &self.border_tiles[tile * 32..tile * 32 + 32];
+                for row in 0..8 {
+                    let line = if y_flip { 7 - row } else { row };
+                    for column in 0..8 {
+                        let bit = if x_flip { column } else { 7 - column };
+                        // snes 4bpp tiles keep planes 0/1 and 2/3 in separate halves
+                        let color = ((data[line * 2] >> bit) & 0x1)
+                            | (((data[line * 2 + 1] >> bit) & 0x1) << 1)
+                            | (((data[16 + line * 2] >> bit) & 0x1) << 2)
+                            | (((data[16 + line * 2 + 1] >> bit) & 0x1) << 3);
+                        // color 0 is transparent
+                        if color == 0 {
+                            continue;
+                        }
+                        let address = (tile_y * 8 + row) * BORDER_WIDTH + tile_x * 8 + column;
+                        frame[address] = rgb555_to_rgb888(self.border_palettes[palette][color as usize]);
+                    }
+                }
+            }
+        }
+    }
+}
+
+// copies the 160x144 screen into the middle of a border frame
+pub fn draw_screen(frame: &mut [u32], screen: &[u32]) {
+    let left = (BORDER_WIDTH - SCREEN_WIDTH) / 2;
+    let top = (BORDER_HEIGHT - SCREEN_HEIGHT) / 2;
+    for y in 0..SCREEN_HEIGHT {
+        let start = (top + y) * BORDER_WIDTH + left;
+        frame[start..start + SCREEN_WIDTH].copy_from_slice(&screen[y * SCREEN_WIDTH..(y + 1) * SCREEN_WIDTH]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // one ATTR_BLK set over the cells 2,2 to 5,5 with inside 1, border 2 and outside 3
+    fn attribute_block(control: u8) -> Sgb {
+        let mut sgb = Sgb::new();
+        let mut data = [0; PACKET_SIZE];
+        data[0] = 0x04 << 3 | 1;
+        data[1] = 1;
+        data[2..8].copy_from_slice(&[control, 0x1 | 0x2 << 2 | 0x3 << 4, 2, 2, 5, 5]);
+        sgb.attribute_block(&data);
+        sgb
+    }
+
+    fn cell(sgb: &Sgb, x: usize, y: usize) -> u8 {
+        sgb.attribute_map[y * ATTRIBUTE_WIDTH + x]
+    }
+
+    #[test]
+    fn inside_only_colours_the_border_with_the_inside() {
+        let sgb = attribute_block(0x1);
+        assert_eq!(cell(&sgb, 3, 3), 1);
+        assert_eq!(cell(&sgb, 2, 2), 1);
+        assert_eq!(cell(&sgb, 5, 4), 1);
+        assert_eq!(cell(&sgb, 0, 0), 0);
+    }
+
+    #[test]
+    fn outside_only_colours_the_border_with_the_outside() {
+        let sgb = attribute_block(0x4);
+        assert_eq!(cell(&sgb, 3, 3), 0);
+        assert_eq!(cell(&sgb, 2, 2), 3);
+        assert_eq!(cell(&sgb, 5, 4), 3);
+        assert_eq!(cell(&sgb, 0, 0), 3);
+    }
+
+    #[test]
+    fn inside_and_outside_leave_the_border() {
+        let sgb = attribute_block(0x5);
+        assert_eq!(cell(&sgb, 3, 3), 1);
+        assert_eq!(cell(&sgb, 2, 2), 0);
+        assert_eq!(cell(&sgb, 5, 4), 0);
+        assert_eq!(cell(&sgb, 0, 0), 3);
+    }
+
+    #[test]
+    fn the_border_is_drawn_into_the_frame_it_is_given() {
+        let sgb = Sgb::new();
+        let backdrop = rgb555_to_rgb888(sgb.palettes[0][0]);
+        let mut frame = vec![];
+        sgb.draw_border(&mut frame, &[0x123456; SCREEN_WIDTH * SCREEN_HEIGHT]);
+        assert_eq!(frame.len(), BORDER_WIDTH * BORDER_HEIGHT);
+        assert_eq!(frame[0], backdrop);
+        assert_eq!(frame[40 * BORDER_WIDTH + 48], 0x123456);
+
+        let buffer = frame.as_ptr();
+        sgb.draw_border(&mut frame, &[0x654321; SCREEN_WIDTH * SCREEN_HEIGHT]);
+        assert_eq!(frame.as_ptr(), buffer);
+        assert_eq!(frame.len(), BORDER_WIDTH * BORDER_HEIGHT);
+        assert_eq!(frame[0], backdrop);
+        assert_eq!(frame[183 * BORDER_WIDTH + 207], 0x654321);
+    }
+}
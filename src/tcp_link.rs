@@ -0,0 +1,174 @@
+use crate::serial::Link;
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// every message is a kind, the number of the transfer it belongs to and the byte on the line
+const TRANSFER: u8 = 0; // sent by the side providing the clock
+const REPLY: u8 = 1; // the other side's byte in return
+const COMMIT: u8 = 2; // the reply arrived, the other side can take the byte
+const CANCEL: u8 = 3; // the reply was too late, neither side takes a byte
+// a peer that's paused or hung doesn't hold this side up longer than a few frames
+const REPLY_TIMEOUT: Duration = Duration::from_millis(50);
+
+// link cable to another emulator over tcp, the side with the internal clock
+// waits for the reply so both ends exchange each byte in lockstep. the other side
+// only takes the byte once the reply is known to have made it in time
+pub struct TcpLink {
+    stream: TcpStream,
+    messages: Receiver<[u8; 3]>,
+    next_transfer: u8,
+    pending: Option<(u8, u8)>, // a transfer clocked by the other side and its byte, waiting to be committed
+}
+
+impl TcpLink {
+    // waits for the other emulator to connect
+    pub fn listen(address: &str) -> io::Result<Self> {
+        let listener = TcpListener::bind(address)?;
+        println!("waiting for link on {}", listener.local_addr()?);
+        let (stream, peer) = listener.accept()?;
+        println!("linked with {}", peer);
+        TcpLink::new(stream)
+    }
+
+    pub fn connect(address: &str) -> io::Result<Self> {
+        let stream = TcpStream::connect(address)?;
+        println!("linked with {}", address);
+        TcpLink::new(stream)
+    }
+
+    fn new(stream: TcpStream) -> io::Result<Self> {
+        stream.set_nodelay(true)?;
+        let mut reader = stream.try_clone()?;
+        let (sender, messages) = mpsc::channel();
+        // messages are read on their own thread so polling never blocks
+        thread::spawn(move || {
+            let mut message = [0; 3];
+            while reader.read_exact(&mut message).is_ok() {
+                if sender.send(message).is_err() {
+                    break;
+                }
+            }
+        });
+        Ok(Self {
+            stream,
+            messages,
+            next_transfer: 0,
+            pending: None,
+        })
+    }
+
+    fn send(&mut self, kind: u8, transfer: u8, byte: u8) {
+        // a closed connection acts like an unplugged cable
+        let _ = self.stream.write_all(&[kind, transfer, byte]);
+    }
+}
+
+impl Link for TcpLink {
+    fn transfer(&mut self, byte: u8) -> u8 {
+        let transfer = self.next_transfer;
+        self.next_transfer = self.next_transfer.wrapping_add(1);
+        self.pending = None;
+        self.send(TRANSFER, transfer, byte);
+        let deadline = Instant::now() + REPLY_TIMEOUT;
+        loop {
+            let timeout = deadline.saturating_duration_since(Instant::now());
+            match self.messages.recv_timeout(timeout) {
+                Ok([REPLY, number, reply]) if number == transfer => {
+                    self.send(COMMIT, transfer, 0);
+                    return reply;
+                }
+                // both sides are providing a clock, neither sees the other's byte
+                Ok([TRANSFER, number, _]) => self.send(REPLY, number, 0xff),
+                // replies to cancelled transfers
+                Ok(_) => (),
+                // no reply in time reads like nothing is plugged in, on both sides
+                Err(RecvTimeoutError::Timeout) => {
+                    self.send(CANCEL, transfer, 0);
+                    return 0xff;
+                }
+                Err(RecvTimeoutError::Disconnected) => return 0xff,
+            }
+        }
+    }
+
+    fn poll(&mut self, byte: u8) -> Option<u8> {
+        while let Ok(message) = self.messages.try_recv() {
+            match message {
+                [TRANSFER, number, incoming] => {
+                    self.send(REPLY, number, byte);
+                    self.pending = Some((number, incoming));
+                }
+                [COMMIT, number, _] => {
+                    if let Some((pending, incoming)) = self.pending {
+                        if pending == number {
+                            self.pending = None;
+                            return Some(incoming);
+                        }
+                    }
+                }
+                [CANCEL, number, _] if self.pending.map(|(pending, _)| pending) == Some(number) => {
+                    self.pending = None
+                }
+                _ => (),
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a listening and a connected link over localhost
+    fn linked() -> (TcpLink, TcpLink) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let accepting = thread::spawn(move || TcpLink::new(listener.accept().unwrap().0).unwrap());
+        let connected = TcpLink::connect(&address).unwrap();
+        (accepting.join().unwrap(), connected)
+    }
+
+    // polls like a console waiting on the external clock until a byte comes in
+    fn poll_until_byte(mut link: TcpLink, byte: u8) -> thread::JoinHandle<(TcpLink, u8)> {
+        thread::spawn(move || loop {
+            if let Some(incoming) = link.poll(byte) {
+                return (link, incoming);
+            }
+            thread::sleep(Duration::from_millis(1));
+        })
+    }
+
+    #[test]
+    fn transfers_swap_bytes() {
+        let (mut master, slave) = linked();
+        let slave = poll_until_byte(slave, 0x34);
+        assert_eq!(master.transfer(0x12), 0x34);
+        let (mut slave, incoming) = slave.join().unwrap();
+        assert_eq!(incoming, 0x12);
+
+        // and the other way around
+        let master = poll_until_byte(master, 0x78);
+        assert_eq!(slave.transfer(0x56), 0x78);
+        assert_eq!(master.join().unwrap().1, 0x56);
+    }
+
+    #[test]
+    fn a_late_reply_cancels_the_transfer_on_both_sides() {
+        let (mut master, mut slave) = linked();
+        assert_eq!(master.transfer(0x12), 0xff);
+        // the slave replies to the transfer and then sees it cancelled
+        thread::sleep(Duration::from_millis(20));
+        assert_eq!(slave.poll(0x34), None);
+        thread::sleep(Duration::from_millis(20));
+        assert_eq!(slave.poll(0x34), None);
+
+        // the late reply isn't taken for the next transfer's
+        let slave = poll_until_byte(slave, 0x78);
+        assert_eq!(master.transfer(0x56), 0x78);
+        assert_eq!(slave.join().unwrap().1, 0x56);
+    }
+}
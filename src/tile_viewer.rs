@@ -0,0 +1,94 @@
+use crate::debug_window::DebugView;
+use crate::mmu::MMU;
+use crate::ppu::DebugPalette;
+
+// the 384 tiles at 0x8000-0x97ff, 16 to a row with a line between them.
+// cgb shows the second bank to the right of the first
+const COLUMNS: usize = 16;
+const ROWS: usize = 24;
+const CELL: usize = 9; // a tile and the grid line before it
+const BANK_WIDTH: usize = COLUMNS * CELL;
+const HEIGHT: usize = ROWS * CELL + 1;
+const GRID_COLOR: u32 = 0x808080;
+
+pub struct TileViewer {
+    palette: DebugPalette,
+    banks: usize,
+}
+
+impl TileViewer {
+    pub fn new(palette: DebugPalette, cgb: bool) -> Self {
+        Self {
+            palette,
+            banks: if cgb { 2 } else { 1 },
+        }
+    }
+}
+
+impl DebugView for TileViewer {
+    fn title(&self) -> String {
+        format!("Tiles ({}) 1-4 change palette", palette_name(self.palette))
+    }
+
+    fn size(&self) -> (usize, usize) {
+        (BANK_WIDTH * self.banks + 1, HEIGHT)
+    }
+
+    fn render(&self, mmu: &MMU) -> Vec<u32> {
+        let ppu = &mmu.ppu;
+        let (width, height) = self.size();
+        let mut buffer = vec![GRID_COLOR; width * height];
+        let colors = ppu.debug_colors(self.palette);
+        for bank in 0..self.banks {
+            for tile in 0..COLUMNS * ROWS {
+                let left = bank * BANK_WIDTH + (tile % COLUMNS) * CELL + 1;
+                let top = (tile / COLUMNS) * CELL + 1;
+                for row in 0..8 {
+                    for (x, color) in ppu.tile_row(bank, tile * 16, row).iter().enumerate() {
+                        buffer[(top + row) * width + left + x] = colors[*color as usize];
+                    }
+                }
+            }
+        }
+        buffer
+    }
+
+    fn key_pressed(&mut self, _mmu: &mut MMU, key: minifb::Key) {
+        self.palette = match key {
+            minifb::Key::Key1 => DebugPalette::Bgp,
+            minifb::Key::Key2 => DebugPalette::Obp0,
+            minifb::Key::Key3 => DebugPalette::Obp1,
+            minifb::Key::Key4 => DebugPalette::Grey,
+            _ => self.palette,
+        };
+    }
+
+    fn hover(&self, _mmu: &MMU, x: usize, y: usize) -> Option<String> {
+        let bank = x / BANK_WIDTH;
+        let (column, row) = ((x % BANK_WIDTH) / CELL, y / CELL);
+        if bank >= self.banks || row >= ROWS {
+            return None;
+        }
+        let tile = row * COLUMNS + column;
+        Some(format!("tile {:#05x} at {}:{:04x}", tile, bank, 0x8000 + tile * 16))
+    }
+}
+
+pub fn palette_from_name(name: &str) -> Option<DebugPalette> {
+    match name.to_lowercase().as_str() {
+        "bgp" => Some(DebugPalette::Bgp),
+        "obp0" => Some(DebugPalette::Obp0),
+        "obp1" => Some(DebugPalette::Obp1),
+        "grey" | "gray" => Some(DebugPalette::Grey),
+        _ => None,
+    }
+}
+
+fn palette_name(palette: DebugPalette) -> &'static str {
+    match palette {
+        DebugPalette::Bgp => "bgp",
+        DebugPalette::Obp0 => "obp0",
+        DebugPalette::Obp1 => "obp1",
+        DebugPalette::Grey => "grey",
+    }
+}
@@ -0,0 +1,87 @@
+use crate::debug_window::DebugView;
+use crate::mmu::MMU;
+use crate::utills::check_bit;
+
+// both 32x32 tilemaps side by side, 0x9800 on the left and 0x9c00 on the right, drawn
+// the way the background reads them. the screen's view of the background is outlined
+// in red and the part of the window that's shown in blue, both wrap like the ppu does
+const MAP_SIZE: usize = 256;
+const GAP: usize = 8;
+const WIDTH: usize = MAP_SIZE * 2 + GAP;
+const TILEMAPS: [u16; 2] = [0x1800, 0x1c00]; // offsets into vram
+const GAP_COLOR: u32 = 0x808080;
+const VIEWPORT_COLOR: u32 = 0xff0000;
+const WINDOW_COLOR: u32 = 0x0000ff;
+
+pub struct TilemapViewer;
+
+impl DebugView for TilemapViewer {
+    fn title(&self) -> String {
+        "Tilemaps 9800 and 9c00".to_string()
+    }
+
+    fn size(&self) -> (usize, usize) {
+        (WIDTH, MAP_SIZE)
+    }
+
+    fn render(&self, mmu: &MMU) -> Vec<u32> {
+        let ppu = &mmu.ppu;
+        let mut buffer = vec![GAP_COLOR; WIDTH * MAP_SIZE];
+        for (map, tilemap) in TILEMAPS.iter().enumerate() {
+            let left = map * (MAP_SIZE + GAP);
+            for y in 0..MAP_SIZE {
+                for x in 0..MAP_SIZE {
+                    buffer[y * WIDTH + left + x] = ppu.tilemap_color(*tilemap, x as u8, y as u8);
+                }
+            }
+        }
+
+        let lcdc = ppu.read_byte(0xff40);
+        let background_map = if check_bit(lcdc, 3) { 1 } else { 0 };
+        let window_map = if check_bit(lcdc, 6) { 1 } else { 0 };
+        let (scx, scy) = (ppu.read_byte(0xff43) as usize, ppu.read_byte(0xff42) as usize);
+        outline(&mut buffer, background_map, scx, scy, 160, 144, VIEWPORT_COLOR);
+        // the window starts at its map's top left corner and covers the screen from wx-7, wy
+        let (wx, wy) = (ppu.read_byte(0xff4b) as usize, ppu.read_byte(0xff4a) as usize);
+        if check_bit(lcdc, 5) && wx < 167 && wy < 144 {
+            let width = 160 - wx.saturating_sub(7);
+            outline(&mut buffer, window_map, 0, 0, width, 144 - wy, WINDOW_COLOR);
+        }
+        buffer
+    }
+
+    fn hover(&self, mmu: &MMU, x: usize, y: usize) -> Option<String> {
+        let ppu = &mmu.ppu;
+        let map = x / (MAP_SIZE + GAP);
+        let x = x % (MAP_SIZE + GAP);
+        if map > 1 || x >= MAP_SIZE || y >= MAP_SIZE {
+            return None;
+        }
+        let (column, row) = ((x / 8) as u8, (y / 8) as u8);
+        let (tile, attributes) = ppu.tilemap_entry(TILEMAPS[map], column, row);
+        let entry = 0x8000 + TILEMAPS[map] as usize + row as usize * 32 + column as usize;
+        let data = 0x8000 + ppu.tile_data_address(tile) as usize;
+        let bank = if check_bit(attributes, 3) { 1 } else { 0 };
+        let mut text = format!("{},{} at {:04x} tile {:#04x} data {}:{:04x}", column, row, entry, tile, bank, data);
+        if ppu.is_cgb() {
+            text += &format!(" attributes {:#04x}", attributes);
+        }
+        Some(text)
+    }
+}
+
+// a rectangle on one of the maps, wrapping around its edges
+fn outline(buffer: &mut [u32], map: usize, left: usize, top: usize, width: usize, height: usize, color: u32) {
+    let map_left = map * (MAP_SIZE + GAP);
+    let mut plot = |x: usize, y: usize| {
+        buffer[(y % MAP_SIZE) * WIDTH + map_left + x % MAP_SIZE] = color;
+    };
+    for x in left..left + width {
+        plot(x, top);
+        plot(x, top + height - 1);
+    }
+    for y in top..top + height {
+        plot(left, y);
+        plot(left + width - 1, y);
+    }
+}
@@ -1,78 +1,207 @@
+use crate::state::{StateReader, StateWriter};
+use crate::utills::check_bit;
+
 pub struct Timer {
-    div: u8, // divide register
+    counter: u16, // system counter, div is the upper 8 bits
     tima: u8, // timer counter
     tma: u8, // timer overflow modulo
     tac: u8, // timer control
-    clocks_since_div_inc: u32,
-    clocks_since_tima_inc: u32,
-    enabled: bool,
-    speed: u32,
+    overflowed: bool, // tima overflowed and reads 0 until the reload
+    reloading: bool, // tma is being loaded into tima this m-cycle
     pub interrupt: u8
 }
 
 impl Timer {
   pub fn new() -> Self {
         Self {
-            div: 0,
+            counter: 0,
             tima: 0,
             tma: 0,
             tac: 0,
-            clocks_since_div_inc: 0,
-            clocks_since_tima_inc: 0,
-            enabled: false,
-            speed: 0,
+            overflowed: false,
+            reloading: false,
             interrupt: 0
         }
     }
 
     pub fn read_byte(&self, address: u16) -> u8 {
         match address {
-            0xff04 => self.div,
+            0xff04 => (self.counter >> 8) as u8,
             0xff05 => self.tima,
             0xff06 => self.tma,
-            0xff07 => self.tac,
+            0xff07 => 0xf8 | self.tac, // upper bits are unused
             _=> unreachable!("address {} is not handled by timer", address)
         }
     }
 
     pub fn write_byte(&mut self, address: u16, value: u8) {
         match address {
-            0xff04 => self.div = 0, // div is reset when writen to
-            0xff05 => self.tima = value,
-            0xff06 => self.tma = value,
+            0xff04 => {
+                // resetting the counter can cause a falling edge
+                let old_signal = self.timer_signal();
+                self.counter = 0;
+                self.detect_falling_edge(old_signal);
+            }
+            0xff05 => {
+                // a write during the delay cancels the reload, during the reload it is ignored
+                if !self.reloading {
+                    self.tima = value;
+                    self.overflowed = false;
+                }
+            }
+            0xff06 => {
+                self.tma = value;
+                if self.reloading {
+                    self.tima = value;
+                }
+            }
             0xff07 => {
-                self.enabled = value & 0x4 == 0x4;
-                self.speed = match value & 0x3 {
-                    0 => 1024,
-                    1 => 16,
-                    2 => 64,
-                    _ => 256
-                };
-                self.tac = value;
+                // switching the selected bit or disabling can also cause a falling edge
+                let old_signal = self.timer_signal();
+                self.tac = value & 0x7;
+                self.detect_falling_edge(old_signal);
             },
             _=> unreachable!("address {} is not handled by timer", address)
         }
     }
 
+    pub fn save_state(&self, state: &mut StateWriter) {
+        state.write_u16(self.counter);
+        state.write_u8(self.tima);
+        state.write_u8(self.tma);
+        state.write_u8(self.tac);
+        state.write_bool(self.overflowed);
+        state.write_bool(self.reloading);
+    }
+
+    pub fn load_state(&mut self, state: &mut StateReader) -> Result<(), String> {
+        self.counter = state.read_u16()?;
+        self.tima = state.read_u8()?;
+        self.tma = state.read_u8()?;
+        self.tac = state.read_u8()?;
+        self.overflowed = state.read_bool()?;
+        self.reloading = state.read_bool()?;
+        Ok(())
+    }
+
     pub fn tick(&mut self, clocks: u32) {
         self.interrupt = 0; // reset interrupt
-        self.clocks_since_div_inc += clocks; 
-        if self.clocks_since_div_inc >= 256 {
-            self.div = self.div.wrapping_add(1); // incs once every 256 cpu clocks
-            self.clocks_since_div_inc -= 256;
+        for _ in 0..clocks / 4 {
+            self.step();
         }
+    }
 
-        if self.enabled {
-            self.clocks_since_tima_inc += clocks;
-            if self.clocks_since_tima_inc >= self.speed { 
-                self.tima = self.tima.wrapping_add(1); // incs once every (self.speed) cpu clocks 
-                self.clocks_since_tima_inc -= self.speed;
-    
-                if self.tima == 0 { // interrupt if overflow, should wait one cycle before interrupt is thrown
-                    self.tima = self.tma;
-                    self.interrupt = 4;
-                }
+    // advance one m-cycle
+    fn step(&mut self) {
+        self.reloading = false;
+        // tima is reloaded and the interrupt thrown one m-cycle after the overflow
+        if self.overflowed {
+            self.overflowed = false;
+            self.tima = self.tma;
+            self.interrupt = 4;
+            self.reloading = true;
+        }
+
+        let old_signal = self.timer_signal();
+        self.counter = self.counter.wrapping_add(4);
+        self.detect_falling_edge(old_signal);
+    }
+
+    // the counter bit selected by tac, and-ed with the enable bit
+    fn timer_signal(&self) -> bool {
+        let bit = match self.tac & 0x3 {
+            0 => 9, // 1024 clocks
+            1 => 3, // 16 clocks
+            2 => 5, // 64 clocks
+            _ => 7, // 256 clocks
+        };
+        check_bit(self.tac, 2) && self.counter & (1 << bit) != 0
+    }
+
+    fn detect_falling_edge(&mut self, old_signal: bool) {
+        if old_signal && !self.timer_signal() {
+            let (tima, overflowed) = self.tima.overflowing_add(1);
+            self.tima = tima;
+            if overflowed {
+                self.overflowed = true;
             }
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // counting every 16 clocks, tima overflows on the next falling edge
+    fn about_to_overflow() -> Timer {
+        let mut timer = Timer::new();
+        timer.write_byte(0xff05, 0xff);
+        timer.write_byte(0xff06, 0x80);
+        timer.write_byte(0xff07, 0x05);
+        timer.tick(16);
+        timer
+    }
+
+    #[test]
+    fn resetting_div_can_tick_tima() {
+        let mut timer = Timer::new();
+        timer.write_byte(0xff07, 0x05);
+        timer.tick(8);
+        timer.write_byte(0xff04, 0);
+        assert_eq!(timer.read_byte(0xff05), 1);
+        // with the selected bit low there is no edge
+        timer.write_byte(0xff04, 0);
+        assert_eq!(timer.read_byte(0xff05), 1);
+    }
+
+    #[test]
+    fn changing_tac_can_tick_tima() {
+        let mut timer = Timer::new();
+        timer.write_byte(0xff07, 0x05);
+        timer.tick(8);
+        // selecting a bit that is low
+        timer.write_byte(0xff07, 0x06);
+        assert_eq!(timer.read_byte(0xff05), 1);
+
+        timer.write_byte(0xff07, 0x05);
+        // disabling while the selected bit is high
+        timer.write_byte(0xff07, 0x01);
+        assert_eq!(timer.read_byte(0xff05), 2);
+    }
+
+    #[test]
+    fn tima_reloads_an_m_cycle_after_overflowing() {
+        let mut timer = about_to_overflow();
+        assert_eq!(timer.read_byte(0xff05), 0);
+        assert_eq!(timer.interrupt, 0);
+        timer.tick(4);
+        assert_eq!(timer.read_byte(0xff05), 0x80);
+        assert_eq!(timer.interrupt, 4);
+    }
+
+    #[test]
+    fn writing_tima_before_the_reload_cancels_it() {
+        let mut timer = about_to_overflow();
+        timer.write_byte(0xff05, 0x42);
+        timer.tick(4);
+        assert_eq!(timer.read_byte(0xff05), 0x42);
+        assert_eq!(timer.interrupt, 0);
+    }
+
+    #[test]
+    fn writes_during_the_reload() {
+        let mut timer = about_to_overflow();
+        timer.tick(4);
+        // tima keeps the reloaded value, a new tma goes straight through
+        timer.write_byte(0xff05, 0x42);
+        assert_eq!(timer.read_byte(0xff05), 0x80);
+        timer.write_byte(0xff06, 0x99);
+        assert_eq!(timer.read_byte(0xff05), 0x99);
+
+        // and after it tima can be written again
+        timer.tick(4);
+        timer.write_byte(0xff05, 0x42);
+        assert_eq!(timer.read_byte(0xff05), 0x42);
+    }
+}
@@ -10,3 +10,11 @@ pub fn get_bit_value(byte: u8, bit_position: u8) -> u8 {
     }
 }
 
+// 15 bit colors as used by the cgb and sgb, 5 bits per channel
+pub fn rgb555_to_rgb888(color: u16) -> u32 {
+    let scale = |value: u16| ((value << 3) | (value >> 2)) as u32;
+    let red = color & 0x1f;
+    let green = (color >> 5) & 0x1f;
+    let blue = (color >> 10) & 0x1f;
+    (scale(red) << 16) | (scale(green) << 8) | scale(blue)
+}
@@ -0,0 +1,204 @@
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+
+// the game boy runs 4194304 clocks a second and 70224 a frame, about 59.73 frames a second
+const CLOCK_SPEED: u32 = 4194304;
+const CLOCKS_PER_FRAME: u32 = crate::gameboy::CLOCKS_PER_FRAME;
+
+// writes every emulated frame to a video, the format is picked by the file's extension.
+// there is no sound yet, once there is it should go in a wav next to the video
+pub struct VideoRecorder {
+    encoder: Encoder,
+    width: usize,
+    height: usize,
+    frames: u32,
+}
+
+enum Encoder {
+    Gif(gif::Encoder<BufWriter<File>>),
+    Apng(BufWriter<File>),
+    Y4m(BufWriter<File>),
+}
+
+impl VideoRecorder {
+    // .gif, .png or .apng, and .y4m for raw video to pipe into other tools
+    pub fn create(path: &str, width: usize, height: usize) -> Result<Self, String> {
+        let error = |e: &dyn std::fmt::Display| format!("{}: {}", path, e);
+        let extension = path.rsplit('.').next().unwrap_or("").to_lowercase();
+        if !["gif", "png", "apng", "y4m"].contains(&extension.as_str()) {
+            return Err(format!("{}: videos can be .gif, .png, .apng or .y4m", path));
+        }
+        let file = BufWriter::new(File::create(path).map_err(|e| error(&e))?);
+        let encoder = match extension.as_str() {
+            "gif" => {
+                let mut encoder = gif::Encoder::new(file, width as u16, height as u16, &[]).map_err(|e| error(&e))?;
+                encoder.set_repeat(gif::Repeat::Infinite).map_err(|e| error(&e))?;
+                Encoder::Gif(encoder)
+            }
+            "y4m" => {
+                let mut file = file;
+                // full resolution chroma, with the frame rate as an exact fraction
+                writeln!(file, "YUV4MPEG2 W{} H{} F{}:{} Ip A1:1 C444", width, height, CLOCK_SPEED, CLOCKS_PER_FRAME)
+                    .map_err(|e| error(&e))?;
+                Encoder::Y4m(file)
+            }
+            _ => {
+                let mut file = file;
+                write_apng_header(&mut file, width, height).map_err(|e| error(&e))?;
+                Encoder::Apng(file)
+            }
+        };
+        Ok(Self {
+            encoder,
+            width,
+            height,
+            frames: 0,
+        })
+    }
+
+    // a frame of 0xrrggbb pixels
+    pub fn record_frame(&mut self, buffer: &[u32]) -> Result<(), String> {
+        match &mut self.encoder {
+            Encoder::Gif(encoder) => {
+                // gif delays are in hundredths of a second, so they alternate to keep the pace
+                let delay = gif_time(self.frames + 1) - gif_time(self.frames);
+                let mut frame = gif_frame(buffer, self.width, self.height);
+                frame.delay = delay as u16;
+                encoder.write_frame(&frame).map_err(|e| e.to_string())?;
+            }
+            Encoder::Apng(file) => {
+                write_apng_frame(file, buffer, self.width, self.height, self.frames).map_err(|e| e.to_string())?;
+            }
+            Encoder::Y4m(file) => write_y4m_frame(file, buffer).map_err(|e| e.to_string())?,
+        }
+        self.frames += 1;
+        Ok(())
+    }
+
+    // writes anything buffered, apngs only know how many frames they have at the end
+    pub fn finish(self) -> Result<(), String> {
+        match self.encoder {
+            Encoder::Gif(encoder) => encoder.into_inner().and_then(|mut file| file.flush()),
+            Encoder::Apng(file) => finish_apng(file, self.frames),
+            Encoder::Y4m(mut file) => file.flush(),
+        }
+        .map_err(|e| e.to_string())
+    }
+}
+
+// hundredths of a second at the start of a frame
+fn gif_time(frame: u32) -> u64 {
+    (frame as u64 * CLOCKS_PER_FRAME as u64 * 100 + CLOCK_SPEED as u64 / 2) / CLOCK_SPEED as u64
+}
+
+// game boy frames rarely have more than a few dozen colours, so they get an exact
+// palette and only fall back to quantizing when there are more than a gif can hold
+fn gif_frame(buffer: &[u32], width: usize, height: usize) -> gif::Frame<'static> {
+    let mut colours = HashMap::new();
+    let mut palette = vec![];
+    let mut indices = Vec::with_capacity(buffer.len());
+    for pixel in buffer {
+        let next = colours.len();
+        let index = *colours.entry(*pixel).or_insert_with(|| {
+            palette.extend_from_slice(&pixel.to_be_bytes()[1..]);
+            next
+        });
+        if colours.len() > 256 {
+            let rgb: Vec<u8> = buffer.iter().flat_map(|pixel| pixel.to_be_bytes()[1..].to_vec()).collect();
+            return gif::Frame::from_rgb(width as u16, height as u16, &rgb);
+        }
+        indices.push(index as u8);
+    }
+    let mut frame = gif::Frame::from_palette_pixels(width as u16, height as u16, indices, palette, None);
+    frame.dispose = gif::DisposalMethod::Keep;
+    frame
+}
+
+// the acTL chunk follows the 8 byte signature and the 25 byte IHDR chunk, its frame
+// count is patched in once recording stops
+const ACTL_OFFSET: u64 = 33;
+
+fn write_chunk(file: &mut impl Write, kind: &[u8; 4], data: &[u8]) -> io::Result<()> {
+    let mut crc = crc32fast::Hasher::new();
+    crc.update(kind);
+    crc.update(data);
+    file.write_all(&(data.len() as u32).to_be_bytes())?;
+    file.write_all(kind)?;
+    file.write_all(data)?;
+    file.write_all(&crc.finalize().to_be_bytes())
+}
+
+fn write_apng_header(file: &mut impl Write, width: usize, height: usize) -> io::Result<()> {
+    file.write_all(b"\x89PNG\r\n\x1a\n")?;
+    let mut header = vec![];
+    header.extend_from_slice(&(width as u32).to_be_bytes());
+    header.extend_from_slice(&(height as u32).to_be_bytes());
+    // 8 bit rgb, deflate, no filtering method, not interlaced
+    header.extend_from_slice(&[8, 2, 0, 0, 0]);
+    write_chunk(file, b"IHDR", &header)?;
+    write_chunk(file, b"acTL", &[0; 8])
+}
+
+// the first frame is the default image so viewers without apng support show it
+fn write_apng_frame(file: &mut impl Write, buffer: &[u32], width: usize, height: usize, frame: u32) -> io::Result<()> {
+    // fcTL and fdAT chunks share one sequence of numbers
+    let sequence = if frame == 0 { 0 } else { frame * 2 - 1 };
+    let mut control = vec![];
+    control.extend_from_slice(&sequence.to_be_bytes());
+    control.extend_from_slice(&(width as u32).to_be_bytes());
+    control.extend_from_slice(&(height as u32).to_be_bytes());
+    control.extend_from_slice(&[0; 8]); // x and y offsets
+    // the delay is a fraction of 16 bit numbers, 70224/4194304 only reduces to
+    // 4389/262144 so 1000/59727 stands in for it
+    control.extend_from_slice(&1000u16.to_be_bytes());
+    control.extend_from_slice(&59727u16.to_be_bytes());
+    control.extend_from_slice(&[0, 0]); // dispose none, blend source
+    write_chunk(file, b"fcTL", &control)?;
+
+    let mut encoder = ZlibEncoder::new(vec![], Compression::default());
+    for row in buffer.chunks(width) {
+        let mut line = Vec::with_capacity(1 + width * 3);
+        line.push(0); // no filter
+        for pixel in row {
+            line.extend_from_slice(&pixel.to_be_bytes()[1..]);
+        }
+        encoder.write_all(&line)?;
+    }
+    let data = encoder.finish()?;
+    if frame == 0 {
+        write_chunk(file, b"IDAT", &data)
+    } else {
+        let mut chunk = (sequence + 1).to_be_bytes().to_vec();
+        chunk.extend_from_slice(&data);
+        write_chunk(file, b"fdAT", &chunk)
+    }
+}
+
+fn finish_apng(mut file: BufWriter<File>, frames: u32) -> io::Result<()> {
+    write_chunk(&mut file, b"IEND", &[])?;
+    let mut control = frames.to_be_bytes().to_vec();
+    control.extend_from_slice(&0u32.to_be_bytes()); // loops forever
+    file.seek(SeekFrom::Start(ACTL_OFFSET))?;
+    write_chunk(&mut file, b"acTL", &control)?;
+    file.flush()
+}
+
+// bt.601 studio range, every pixel keeps its own chroma
+fn write_y4m_frame(file: &mut impl Write, buffer: &[u32]) -> io::Result<()> {
+    let mut planes = [vec![], vec![], vec![]];
+    for pixel in buffer {
+        let [_, r, g, b] = pixel.to_be_bytes();
+        let (r, g, b) = (r as f32, g as f32, b as f32);
+        planes[0].push((16.0 + 0.257 * r + 0.504 * g + 0.098 * b).round() as u8);
+        planes[1].push((128.0 - 0.148 * r - 0.291 * g + 0.439 * b).round() as u8);
+        planes[2].push((128.0 + 0.439 * r - 0.368 * g - 0.071 * b).round() as u8);
+    }
+    file.write_all(b"FRAME\n")?;
+    for plane in planes {
+        file.write_all(&plane)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,90 @@
+use crate::mmu::MMU;
+use std::fs;
+
+// addresses to keep an eye on, one to a line with # comments. u16 reads a little
+// endian word, the default is a byte:
+// c0a0 player x
+// d000 u16 score
+pub struct Watch {
+    pub address: u16,
+    pub word: bool,
+    pub label: String,
+}
+
+impl Watch {
+    pub fn read(&self, mmu: &MMU) -> u16 {
+        if self.word {
+            mmu.peek_word(self.address)
+        } else {
+            mmu.peek(self.address) as u16
+        }
+    }
+
+    pub fn format(&self, value: u16) -> String {
+        if self.word {
+            format!("{:04x} ({})", value, value)
+        } else {
+            format!("{:02x} ({})", value, value)
+        }
+    }
+}
+
+pub struct WatchList {
+    pub watches: Vec<Watch>,
+    last: Vec<Option<u16>>, // values when changes were last checked
+}
+
+impl WatchList {
+    pub fn load(path: &str) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+        let mut watches = vec![];
+        for (number, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            let watch = parse_line(line).map_err(|e| format!("{} line {}: {}", path, number + 1, e))?;
+            watches.push(watch);
+        }
+        let last = vec![None; watches.len()];
+        Ok(Self { watches, last })
+    }
+
+    // the label of the watch on an address, words cover both their bytes
+    pub fn label(&self, address: u16) -> Option<&str> {
+        self.watches
+            .iter()
+            .find(|watch| watch.address == address || (watch.word && watch.address.wrapping_add(1) == address))
+            .map(|watch| watch.label.as_str())
+    }
+
+    // the watches whose values changed since the last call, all of them the first time
+    pub fn changes(&mut self, mmu: &MMU) -> Vec<(&Watch, u16)> {
+        let mut changes = vec![];
+        for (watch, last) in self.watches.iter().zip(self.last.iter_mut()) {
+            let value = watch.read(mmu);
+            if *last != Some(value) {
+                *last = Some(value);
+                changes.push((watch, value));
+            }
+        }
+        changes
+    }
+}
+
+fn parse_line(line: &str) -> Result<Watch, String> {
+    let mut parts = line.splitn(2, char::is_whitespace);
+    let address = parts.next().unwrap_or("");
+    let digits = address.trim_start_matches("0x").trim_start_matches('$');
+    let address = u16::from_str_radix(digits, 16).map_err(|_| format!("{} is not a hex address", address))?;
+    let rest = parts.next().unwrap_or("").trim();
+    let (word, label) = match rest.split_once(char::is_whitespace) {
+        Some(("u16", label)) => (true, label.trim()),
+        Some(("u8", label)) => (false, label.trim()),
+        _ => (false, rest),
+    };
+    if label.is_empty() || label == "u8" || label == "u16" {
+        return Err(format!("{} should look like address [u8|u16] label", line));
+    }
+    Ok(Watch { address, word, label: label.to_string() })
+}